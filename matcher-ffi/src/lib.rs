@@ -4,6 +4,8 @@ use std::{
     path::PathBuf,
 };
 
+use matcher_lib::matcher::Matcher;
+
 use log::LevelFilter;
 use log4rs::{
     append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRollerBuilder,
@@ -19,6 +21,19 @@ use log4rs::{
 pub const ERR_OK: i32 = 0;
 /// Parameter error
 pub const ERR_PARAM: i32 = 1;
+/// Policy rejected by validation, the previous policy stays active
+pub const ERR_POLICY: i32 = 2;
+/// File system error
+pub const ERR_IO: i32 = 3;
+
+/// Verdict codes, nothing hit
+pub const VERDICT_NONE: i32 = matcher_lib::VERDICT_NONE;
+pub const VERDICT_AUDIT: i32 = matcher_lib::VERDICT_AUDIT;
+pub const VERDICT_ALERT: i32 = matcher_lib::VERDICT_ALERT;
+pub const VERDICT_NOTIFY: i32 = matcher_lib::VERDICT_NOTIFY;
+pub const VERDICT_ENCRYPT: i32 = matcher_lib::VERDICT_ENCRYPT;
+pub const VERDICT_QUARANTINE: i32 = matcher_lib::VERDICT_QUARANTINE;
+pub const VERDICT_BLOCK: i32 = matcher_lib::VERDICT_BLOCK;
 
 fn setup_logger(log_path: Option<String>) -> Result<(), String> {
     let log_path = match log_path {
@@ -105,15 +120,46 @@ pub extern "C" fn init_matcher(
         Err(_) => return ERR_PARAM,
     };
 
-    matcher_lib::init_matcher(str_file_scan_rule, str_file_scan_format);
-    ERR_OK
+    match matcher_lib::init_matcher(str_file_scan_rule, str_file_scan_format) {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_POLICY,
+    }
 }
 
-// #[allow(clippy::not_unsafe_ptr_arg_deref)]
-// #[no_mangle]
-// pub extern "C" fn drop_matcher(pmatcher: *mut c_void) {
-//     unsafe { pmatcher.drop_in_place() }
-// }
+/// Same as `init_matcher`, and output the policy diagnostics as a JSON array.
+/// Release the diagnostics with `drop_result`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn init_matcher_with_diagnostics(
+    pfile_scan_rule: *const c_char,
+    pfile_scan_format: *const c_char,
+    ppdiagnostics: *mut *mut c_char,
+) -> i32 {
+    if ppdiagnostics.is_null() {
+        return ERR_PARAM;
+    }
+
+    let str_file_scan_rule = match unsafe { CStr::from_ptr(pfile_scan_rule).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_scan_format = match unsafe { CStr::from_ptr(pfile_scan_format).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let result = matcher_lib::init_matcher(str_file_scan_rule, str_file_scan_format);
+    match CString::new(matcher_lib::diagnostics_json(&result)) {
+        Ok(cstring_diagnostics) => unsafe { *ppdiagnostics = cstring_diagnostics.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    match result {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_POLICY,
+    }
+}
 
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
@@ -141,6 +187,22 @@ pub extern "C" fn match_rule(
     ERR_OK
 }
 
+/// Output the `config_version` of the default matcher, release it with `drop_result`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn current_config_version(ppversion: *mut *mut c_char) -> i32 {
+    if ppversion.is_null() {
+        return ERR_PARAM;
+    }
+
+    match CString::new(matcher_lib::current_config_version()) {
+        Ok(cstring_version) => unsafe { *ppversion = cstring_version.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn drop_result(presult: *mut c_char) {
@@ -149,6 +211,530 @@ pub extern "C" fn drop_result(presult: *mut c_char) {
     }
 }
 
+/// Create a standalone matcher handle, release it with `matcher_free`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_new(
+    pfile_scan_rule: *const c_char,
+    pfile_scan_format: *const c_char,
+    ppmatcher: *mut *mut Matcher,
+) -> i32 {
+    if ppmatcher.is_null() {
+        return ERR_PARAM;
+    }
+
+    let str_file_scan_rule = match unsafe { CStr::from_ptr(pfile_scan_rule).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_scan_format = match unsafe { CStr::from_ptr(pfile_scan_format).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::new_matcher(str_file_scan_rule, str_file_scan_format) {
+        Ok(matcher) => unsafe { *ppmatcher = Box::into_raw(Box::new(matcher)) },
+        Err(_) => return ERR_POLICY,
+    }
+
+    ERR_OK
+}
+
+/// Same as `matcher_new`, and output the policy diagnostics as a JSON array.
+/// Release the diagnostics with `drop_result`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_new_with_diagnostics(
+    pfile_scan_rule: *const c_char,
+    pfile_scan_format: *const c_char,
+    ppmatcher: *mut *mut Matcher,
+    ppdiagnostics: *mut *mut c_char,
+) -> i32 {
+    if ppmatcher.is_null() || ppdiagnostics.is_null() {
+        return ERR_PARAM;
+    }
+
+    let str_file_scan_rule = match unsafe { CStr::from_ptr(pfile_scan_rule).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_scan_format = match unsafe { CStr::from_ptr(pfile_scan_format).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let result = matcher_lib::new_matcher(str_file_scan_rule, str_file_scan_format);
+    match CString::new(matcher_lib::diagnostics_json(&result)) {
+        Ok(cstring_diagnostics) => unsafe { *ppdiagnostics = cstring_diagnostics.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    match result {
+        Ok(matcher) => unsafe { *ppmatcher = Box::into_raw(Box::new(matcher)) },
+        Err(_) => return ERR_POLICY,
+    }
+
+    ERR_OK
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_match(
+    pmatcher: *const Matcher,
+    praw_result: *const c_char,
+    pfile_path: *const c_char,
+    ppmatch_result: *mut *mut c_char,
+) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_raw_result = match unsafe { CStr::from_ptr(praw_result).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_path = match unsafe { CStr::from_ptr(pfile_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let match_result = matcher_lib::matcher_match(matcher, str_raw_result, str_file_path);
+    match CString::new(match_result) {
+        Ok(cstring_match_result) => unsafe { *ppmatch_result = cstring_match_result.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// Same as `match_rule`, and output the final verdict code, `VERDICT_NONE`
+/// when nothing hit or every hit is suppressed
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn match_rule_with_verdict(
+    praw_result: *const c_char,
+    pfile_path: *const c_char,
+    ppmatch_result: *mut *mut c_char,
+    pverdict: *mut i32,
+) -> i32 {
+    if pverdict.is_null() {
+        return ERR_PARAM;
+    }
+
+    let str_raw_result = match unsafe { CStr::from_ptr(praw_result).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_path = match unsafe { CStr::from_ptr(pfile_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let (match_result, verdict) =
+        matcher_lib::match_rule_with_verdict(str_raw_result, str_file_path);
+    match CString::new(match_result) {
+        Ok(cstring_match_result) => unsafe {
+            *ppmatch_result = cstring_match_result.into_raw();
+            *pverdict = verdict;
+        },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// `match_rule_with_verdict` on a matcher handle
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_match_with_verdict(
+    pmatcher: *const Matcher,
+    praw_result: *const c_char,
+    pfile_path: *const c_char,
+    ppmatch_result: *mut *mut c_char,
+    pverdict: *mut i32,
+) -> i32 {
+    if pverdict.is_null() {
+        return ERR_PARAM;
+    }
+
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_raw_result = match unsafe { CStr::from_ptr(praw_result).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_path = match unsafe { CStr::from_ptr(pfile_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let (match_result, verdict) =
+        matcher_lib::matcher_match_with_verdict(matcher, str_raw_result, str_file_path);
+    match CString::new(match_result) {
+        Ok(cstring_match_result) => unsafe {
+            *ppmatch_result = cstring_match_result.into_raw();
+            *pverdict = verdict;
+        },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// Output why each rule of the default matcher did or did not hit the file,
+/// as JSON, release it with `drop_result`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn explain_match(
+    praw_result: *const c_char,
+    pfile_path: *const c_char,
+    ppexplanation: *mut *mut c_char,
+) -> i32 {
+    let str_raw_result = match unsafe { CStr::from_ptr(praw_result).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_path = match unsafe { CStr::from_ptr(pfile_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let explanation = matcher_lib::explain_match(str_raw_result, str_file_path);
+    match CString::new(explanation) {
+        Ok(cstring_explanation) => unsafe { *ppexplanation = cstring_explanation.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// `explain_match` on a matcher handle
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_explain(
+    pmatcher: *const Matcher,
+    praw_result: *const c_char,
+    pfile_path: *const c_char,
+    ppexplanation: *mut *mut c_char,
+) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_raw_result = match unsafe { CStr::from_ptr(praw_result).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_path = match unsafe { CStr::from_ptr(pfile_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let explanation = matcher_lib::matcher_explain(matcher, str_raw_result, str_file_path);
+    match CString::new(explanation) {
+        Ok(cstring_explanation) => unsafe { *ppexplanation = cstring_explanation.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// Suppress matching hits of the default matcher from now on, the
+/// suppression is a JSON object like the policy's `suppressions` entries
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn add_suppression(psuppression: *const c_char) -> i32 {
+    let str_suppression = match unsafe { CStr::from_ptr(psuppression).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::add_suppression(str_suppression) {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_POLICY,
+    }
+}
+
+/// `add_suppression` on a matcher handle
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_add_suppression(
+    pmatcher: *const Matcher,
+    psuppression: *const c_char,
+) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_suppression = match unsafe { CStr::from_ptr(psuppression).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::matcher_add_suppression(matcher, str_suppression) {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_POLICY,
+    }
+}
+
+/// Drop the runtime suppressions of the default matcher with `id`,
+/// `ERR_PARAM` when none has it
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn remove_suppression(pid: *const c_char) -> i32 {
+    let str_id = match unsafe { CStr::from_ptr(pid).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::remove_suppression(str_id) {
+        0 => ERR_PARAM,
+        _ => ERR_OK,
+    }
+}
+
+/// `remove_suppression` on a matcher handle
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_remove_suppression(pmatcher: *const Matcher, pid: *const c_char) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_id = match unsafe { CStr::from_ptr(pid).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::matcher_remove_suppression(matcher, str_id) {
+        0 => ERR_PARAM,
+        _ => ERR_OK,
+    }
+}
+
+/// Output the runtime suppressions of the default matcher which have not
+/// expired, as a JSON array, release it with `drop_result`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn list_suppressions(ppsuppressions: *mut *mut c_char) -> i32 {
+    if ppsuppressions.is_null() {
+        return ERR_PARAM;
+    }
+
+    match CString::new(matcher_lib::list_suppressions()) {
+        Ok(cstring_suppressions) => unsafe { *ppsuppressions = cstring_suppressions.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// `list_suppressions` on a matcher handle
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_list_suppressions(
+    pmatcher: *const Matcher,
+    ppsuppressions: *mut *mut c_char,
+) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+    if ppsuppressions.is_null() {
+        return ERR_PARAM;
+    }
+
+    match CString::new(matcher_lib::matcher_list_suppressions(matcher)) {
+        Ok(cstring_suppressions) => unsafe { *ppsuppressions = cstring_suppressions.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// Atomically swap the policy of a matcher handle, in-flight matches keep the old one
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_reload(
+    pmatcher: *const Matcher,
+    pfile_scan_rule: *const c_char,
+    pfile_scan_format: *const c_char,
+) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_file_scan_rule = match unsafe { CStr::from_ptr(pfile_scan_rule).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_scan_format = match unsafe { CStr::from_ptr(pfile_scan_format).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::reload_matcher(matcher, str_file_scan_rule, str_file_scan_format) {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_POLICY,
+    }
+}
+
+/// Same as `matcher_reload`, and output the policy diagnostics as a JSON array.
+/// Release the diagnostics with `drop_result`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_reload_with_diagnostics(
+    pmatcher: *const Matcher,
+    pfile_scan_rule: *const c_char,
+    pfile_scan_format: *const c_char,
+    ppdiagnostics: *mut *mut c_char,
+) -> i32 {
+    if ppdiagnostics.is_null() {
+        return ERR_PARAM;
+    }
+
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_file_scan_rule = match unsafe { CStr::from_ptr(pfile_scan_rule).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let str_file_scan_format = match unsafe { CStr::from_ptr(pfile_scan_format).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    let result = matcher_lib::reload_matcher(matcher, str_file_scan_rule, str_file_scan_format);
+    match CString::new(matcher_lib::diagnostics_json(&result)) {
+        Ok(cstring_diagnostics) => unsafe { *ppdiagnostics = cstring_diagnostics.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    match result {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_POLICY,
+    }
+}
+
+/// Output the `config_version` of a matcher handle, release it with `drop_result`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_config_version(
+    pmatcher: *const Matcher,
+    ppversion: *mut *mut c_char,
+) -> i32 {
+    if ppversion.is_null() {
+        return ERR_PARAM;
+    }
+
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    match CString::new(matcher.config_version()) {
+        Ok(cstring_version) => unsafe { *ppversion = cstring_version.into_raw() },
+        Err(_) => return ERR_PARAM,
+    }
+
+    ERR_OK
+}
+
+/// Configure the file digest cache of the default matcher.
+/// An empty `ppersist_path` keeps the cache in memory only. The default
+/// matcher is never freed, call `save_hash_cache` to persist the cache.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn set_hash_cache(capacity: u32, ppersist_path: *const c_char) -> i32 {
+    let str_persist_path = match unsafe { CStr::from_ptr(ppersist_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    match matcher_lib::set_default_hash_cache(capacity as usize, str_persist_path) {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_PARAM,
+    }
+}
+
+/// Persist the file digest cache of the default matcher
+#[no_mangle]
+pub extern "C" fn save_hash_cache() -> i32 {
+    match matcher_lib::save_default_hash_cache() {
+        Ok(_) => ERR_OK,
+        Err(matcher_lib::fs_error::Error::Scanner(_)) => ERR_PARAM,
+        Err(_) => ERR_IO,
+    }
+}
+
+/// Configure the file digest cache of a matcher handle.
+/// An empty `ppersist_path` keeps the cache in memory only.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_set_hash_cache(
+    pmatcher: *const Matcher,
+    capacity: u32,
+    ppersist_path: *const c_char,
+) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    let str_persist_path = match unsafe { CStr::from_ptr(ppersist_path).to_str() } {
+        Ok(str) => str,
+        Err(_) => return ERR_PARAM,
+    };
+
+    matcher_lib::set_hash_cache(matcher, capacity as usize, str_persist_path);
+    ERR_OK
+}
+
+/// Persist the file digest cache of a matcher handle, also done by `matcher_free`
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_save_hash_cache(pmatcher: *const Matcher) -> i32 {
+    let matcher = match unsafe { pmatcher.as_ref() } {
+        Some(matcher) => matcher,
+        None => return ERR_PARAM,
+    };
+
+    match matcher.save_hash_cache() {
+        Ok(_) => ERR_OK,
+        Err(_) => ERR_IO,
+    }
+}
+
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn matcher_free(pmatcher: *mut Matcher) {
+    if !pmatcher.is_null() {
+        unsafe {
+            let _ = Box::from_raw(pmatcher);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -156,7 +742,14 @@ mod tests {
         os::raw::c_char,
     };
 
-    use crate::{drop_result, ERR_OK, ERR_PARAM};
+    use crate::{
+        current_config_version, drop_result, init_matcher_with_diagnostics,
+        matcher_add_suppression, matcher_config_version, matcher_explain, matcher_free,
+        matcher_list_suppressions, matcher_match, matcher_match_with_verdict, matcher_new,
+        matcher_new_with_diagnostics, matcher_reload, matcher_reload_with_diagnostics,
+        matcher_remove_suppression, ERR_OK, ERR_PARAM, ERR_POLICY, VERDICT_ALERT, VERDICT_BLOCK,
+        VERDICT_NONE,
+    };
 
     #[allow(clippy::not_unsafe_ptr_arg_deref)]
     #[no_mangle]
@@ -190,22 +783,316 @@ mod tests {
         }
     }
 
-    // #[test]
-    // fn test_pass_raw_pointer() {
-    //     let p1 = CString::new("p1p1p1p1p1").unwrap();
-    //     let p2 = CString::new("p2p2p2p2p2").unwrap();
-    //     let mut pmatcher = std::ptr::null_mut();
-    //     let ppmatcher = &mut pmatcher;
-    //     init_matcher(p1.as_ptr(), p2.as_ptr(), ppmatcher);
-    //     println!("ppmatcher is null? {}", ppmatcher.is_null());
-    //     unsafe {
-    //         let pmatcher = *ppmatcher;
-    //         println!("pmatcher is null? {}", pmatcher.is_null());
-    //         let ref_matcher = &*(pmatcher as *mut Matcher);
-    //         ref_matcher.test();
-    //         let result = ref_matcher
-    //             .file_security_check("raw_result_string".to_owned(), &PathBuf::from("value"));
-    //         println!("ptr invoke: {result:?}");
-    //     }
-    // }
+    #[test]
+    fn test_matcher_handle() {
+        let rule = CString::new(
+            r#"{"config_version":"1","file_scan_rules":[],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        let format = CString::new(r#"{"format":{}}"#).unwrap();
+        let mut pmatcher = std::ptr::null_mut();
+        assert_eq!(
+            matcher_new(rule.as_ptr(), format.as_ptr(), &mut pmatcher),
+            ERR_OK
+        );
+        assert!(!pmatcher.is_null());
+
+        let raw_result = CString::new(r#"{"categoryId":1,"format":"txt","data":[]}"#).unwrap();
+        let file_path = CString::new("value").unwrap();
+        let mut result_ptr = std::ptr::null_mut();
+        assert_eq!(
+            matcher_match(
+                pmatcher,
+                raw_result.as_ptr(),
+                file_path.as_ptr(),
+                &mut result_ptr
+            ),
+            ERR_OK
+        );
+        unsafe {
+            assert_eq!(CStr::from_ptr(result_ptr).to_str().unwrap(), "");
+        }
+        drop_result(result_ptr);
+
+        let rule = CString::new(
+            r#"{"config_version":"2","file_scan_rules":[],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            matcher_reload(pmatcher, rule.as_ptr(), format.as_ptr()),
+            ERR_OK
+        );
+        let mut version_ptr = std::ptr::null_mut();
+        assert_eq!(matcher_config_version(pmatcher, &mut version_ptr), ERR_OK);
+        unsafe {
+            assert_eq!(CStr::from_ptr(version_ptr).to_str().unwrap(), "2");
+        }
+        drop_result(version_ptr);
+
+        let rule = CString::new(
+            r#"{"config_version":"3","file_scan_rules":[{"id":1,"code":"","level":1,"file_types":[],"min_file_size":10,"max_file_size":1,"check_file_encrypted":false,"check_file_suffix":false,"expr":"(body101 > 1","expr_context":{"variables":{},"without_builtin_functions":false},"md5_check":false}],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            matcher_reload(pmatcher, rule.as_ptr(), format.as_ptr()),
+            ERR_POLICY
+        );
+        let mut version_ptr = std::ptr::null_mut();
+        assert_eq!(matcher_config_version(pmatcher, &mut version_ptr), ERR_OK);
+        unsafe {
+            assert_eq!(CStr::from_ptr(version_ptr).to_str().unwrap(), "2");
+        }
+        drop_result(version_ptr);
+        assert_eq!(
+            matcher_config_version(pmatcher, std::ptr::null_mut()),
+            ERR_PARAM
+        );
+        assert_eq!(current_config_version(std::ptr::null_mut()), ERR_PARAM);
+        matcher_free(pmatcher);
+    }
+
+    #[test]
+    fn test_matcher_explain() {
+        let rule = CString::new(
+            r#"{"config_version":"1","file_scan_rules":[{"id":7,"code":"R7","level":1,"file_types":[],"min_file_size":1,"max_file_size":1000,"check_file_encrypted":false,"check_file_suffix":false,"expr":"body101 > 1","expr_context":{"variables":{},"without_builtin_functions":false},"md5_check":false}],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        let format = CString::new(r#"{"format":{}}"#).unwrap();
+        let mut pmatcher = std::ptr::null_mut();
+        assert_eq!(
+            matcher_new(rule.as_ptr(), format.as_ptr(), &mut pmatcher),
+            ERR_OK
+        );
+
+        let raw_result = CString::new(
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#,
+        )
+        .unwrap();
+        let file_path = CString::new("/nonexistent/value").unwrap();
+        let mut explanation_ptr = std::ptr::null_mut();
+        assert_eq!(
+            matcher_explain(
+                pmatcher,
+                raw_result.as_ptr(),
+                file_path.as_ptr(),
+                &mut explanation_ptr
+            ),
+            ERR_OK
+        );
+        let explanation = unsafe { CStr::from_ptr(explanation_ptr).to_str().unwrap() };
+        assert!(explanation
+            .contains(r#""id":7,"code":"R7","kind":"rule","hit":false,"disposition":"missed""#));
+        assert!(explanation.contains(r#""step":"file_size","passed":false"#));
+        drop_result(explanation_ptr);
+        matcher_free(pmatcher);
+    }
+
+    #[test]
+    fn test_matcher_verdict() {
+        let rule = CString::new(
+            r#"{"config_version":"1","file_scan_rules":[{"id":1,"code":"R1","level":1,"file_types":[],"min_file_size":1,"max_file_size":100000000,"check_file_encrypted":false,"check_file_suffix":false,"expr":"body101 > 0","expr_context":{"variables":{},"without_builtin_functions":false},"md5_check":false,"actions":["alert"]},{"id":2,"code":"R2","level":1,"file_types":[],"min_file_size":1,"max_file_size":100000000,"check_file_encrypted":false,"check_file_suffix":false,"expr":"body101 > 2","expr_context":{"variables":{},"without_builtin_functions":false},"md5_check":false,"actions":["notify","encrypt"]}],"verdict":{"precedence":["alert"]},"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        let format = CString::new(r#"{"format":{}}"#).unwrap();
+        let mut pmatcher = std::ptr::null_mut();
+        assert_eq!(
+            matcher_new(rule.as_ptr(), format.as_ptr(), &mut pmatcher),
+            ERR_OK
+        );
+
+        let raw_result = CString::new(
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#,
+        )
+        .unwrap();
+        let file_path = CString::new(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        let mut result_ptr = std::ptr::null_mut();
+        let mut verdict = VERDICT_NONE;
+        assert_eq!(
+            matcher_match_with_verdict(
+                pmatcher,
+                raw_result.as_ptr(),
+                file_path.as_ptr(),
+                &mut result_ptr,
+                &mut verdict
+            ),
+            ERR_OK
+        );
+        // alert is listed first, encrypt then notify follow the default order
+        assert_eq!(verdict, VERDICT_ALERT);
+        let result = unsafe { CStr::from_ptr(result_ptr).to_str().unwrap() };
+        assert!(result.contains(
+            r#""verdict":{"action":"alert","id":1,"code":"R1","actions":["alert","encrypt","notify"]}"#
+        ));
+        drop_result(result_ptr);
+
+        let raw_result = CString::new(r#"{"categoryId":1,"format":"txt","data":[]}"#).unwrap();
+        let mut result_ptr = std::ptr::null_mut();
+        assert_eq!(
+            matcher_match_with_verdict(
+                pmatcher,
+                raw_result.as_ptr(),
+                file_path.as_ptr(),
+                &mut result_ptr,
+                &mut verdict
+            ),
+            ERR_OK
+        );
+        assert_eq!(verdict, VERDICT_NONE);
+        drop_result(result_ptr);
+        matcher_free(pmatcher);
+    }
+
+    #[test]
+    fn test_matcher_suppression() {
+        let rule = CString::new(
+            r#"{"config_version":"1","file_scan_rules":[{"id":1,"code":"R1","level":1,"file_types":[],"min_file_size":1,"max_file_size":100000000,"check_file_encrypted":false,"check_file_suffix":false,"expr":"body101 > 0","expr_context":{"variables":{},"without_builtin_functions":false},"md5_check":false,"actions":["block"]}],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        let format = CString::new(r#"{"format":{}}"#).unwrap();
+        let mut pmatcher = std::ptr::null_mut();
+        assert_eq!(
+            matcher_new(rule.as_ptr(), format.as_ptr(), &mut pmatcher),
+            ERR_OK
+        );
+
+        let suppression = CString::new(r#"{"rule_ids":[1],"owner":"","reason":"test"}"#).unwrap();
+        assert_eq!(
+            matcher_add_suppression(pmatcher, suppression.as_ptr()),
+            ERR_POLICY
+        );
+        let suppression = CString::new(
+            r#"{"id":"FP-1","path_glob":"**/Cargo.toml","rule_ids":[1],"owner":"soc","reason":"build manifest"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            matcher_add_suppression(pmatcher, suppression.as_ptr()),
+            ERR_OK
+        );
+
+        let raw_result = CString::new(
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#,
+        )
+        .unwrap();
+        let file_path = CString::new(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml")).unwrap();
+        let mut result_ptr = std::ptr::null_mut();
+        let mut verdict = VERDICT_ALERT;
+        assert_eq!(
+            matcher_match_with_verdict(
+                pmatcher,
+                raw_result.as_ptr(),
+                file_path.as_ptr(),
+                &mut result_ptr,
+                &mut verdict
+            ),
+            ERR_OK
+        );
+        assert_eq!(verdict, VERDICT_NONE);
+        let result = unsafe { CStr::from_ptr(result_ptr).to_str().unwrap() };
+        assert!(result.contains(r#""file_securities":[]"#));
+        assert!(result
+            .contains(r#""suppression":{"id":"FP-1","owner":"soc","reason":"build manifest"}"#));
+        drop_result(result_ptr);
+
+        let mut suppressions_ptr = std::ptr::null_mut();
+        assert_eq!(
+            matcher_list_suppressions(pmatcher, &mut suppressions_ptr),
+            ERR_OK
+        );
+        let suppressions = unsafe { CStr::from_ptr(suppressions_ptr).to_str().unwrap() };
+        assert!(suppressions.starts_with(r#"[{"id":"FP-1","#));
+        drop_result(suppressions_ptr);
+        assert_eq!(
+            matcher_list_suppressions(pmatcher, std::ptr::null_mut()),
+            ERR_PARAM
+        );
+
+        let id = CString::new("FP-1").unwrap();
+        assert_eq!(matcher_remove_suppression(pmatcher, id.as_ptr()), ERR_OK);
+        assert_eq!(matcher_remove_suppression(pmatcher, id.as_ptr()), ERR_PARAM);
+        assert_eq!(
+            matcher_match_with_verdict(
+                pmatcher,
+                raw_result.as_ptr(),
+                file_path.as_ptr(),
+                &mut result_ptr,
+                &mut verdict
+            ),
+            ERR_OK
+        );
+        assert_eq!(verdict, VERDICT_BLOCK);
+        drop_result(result_ptr);
+        matcher_free(pmatcher);
+    }
+
+    #[test]
+    fn test_matcher_diagnostics() {
+        let bad_rule = CString::new(
+            r#"{"config_version":"2","file_scan_rules":[{"id":1,"code":"","level":1,"file_types":[],"min_file_size":1,"max_file_size":10,"check_file_encrypted":false,"check_file_suffix":false,"expr":"","expr_context":{"variables":{},"without_builtin_functions":false},"md5_check":false}],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        let format = CString::new(r#"{"format":{}}"#).unwrap();
+        let mut pmatcher = std::ptr::null_mut();
+        let mut diagnostics_ptr = std::ptr::null_mut();
+        assert_eq!(
+            matcher_new_with_diagnostics(
+                bad_rule.as_ptr(),
+                format.as_ptr(),
+                &mut pmatcher,
+                &mut diagnostics_ptr
+            ),
+            ERR_POLICY
+        );
+        assert!(pmatcher.is_null());
+        let diagnostics = unsafe { CStr::from_ptr(diagnostics_ptr).to_str().unwrap() };
+        assert!(diagnostics.contains(r#""path":"$.file_scan_rules[0].code","rule_id":1"#));
+        drop_result(diagnostics_ptr);
+
+        let rule = CString::new(
+            r#"{"config_version":"1","file_scan_rules":[],"file_digital_dictionary":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            matcher_new_with_diagnostics(
+                rule.as_ptr(),
+                format.as_ptr(),
+                &mut pmatcher,
+                &mut diagnostics_ptr
+            ),
+            ERR_OK
+        );
+        unsafe {
+            assert_eq!(CStr::from_ptr(diagnostics_ptr).to_str().unwrap(), "[]");
+        }
+        drop_result(diagnostics_ptr);
+
+        assert_eq!(
+            matcher_reload_with_diagnostics(
+                pmatcher,
+                bad_rule.as_ptr(),
+                format.as_ptr(),
+                &mut diagnostics_ptr
+            ),
+            ERR_POLICY
+        );
+        let diagnostics = unsafe { CStr::from_ptr(diagnostics_ptr).to_str().unwrap() };
+        assert!(diagnostics.contains(r#""reason":"empty code""#));
+        drop_result(diagnostics_ptr);
+
+        let null = std::ptr::null_mut();
+        assert_eq!(
+            init_matcher_with_diagnostics(rule.as_ptr(), format.as_ptr(), null),
+            ERR_PARAM
+        );
+        let mut pother = std::ptr::null_mut();
+        assert_eq!(
+            matcher_new_with_diagnostics(rule.as_ptr(), format.as_ptr(), &mut pother, null),
+            ERR_PARAM
+        );
+        assert!(pother.is_null());
+        assert_eq!(
+            matcher_reload_with_diagnostics(pmatcher, rule.as_ptr(), format.as_ptr(), null),
+            ERR_PARAM
+        );
+        matcher_free(pmatcher);
+    }
 }
@@ -1,3 +1,6 @@
+use std::fmt;
+
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,4 +11,27 @@ pub enum Error {
     Scanner(String),
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
+    #[error("invalid policy: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Policy(Vec<PolicyDiagnostic>),
+}
+
+/// One problem found while loading a policy
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyDiagnostic {
+    /// Which document the problem is in, `rule`, `format` or `suppression`
+    pub document: &'static str,
+    /// JSON path of the offending value, e.g. `$.file_scan_rules[3].expr`
+    pub path: String,
+    pub rule_id: Option<i32>,
+    pub reason: String,
+}
+
+impl fmt::Display for PolicyDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.document, self.path)?;
+        if let Some(rule_id) = self.rule_id {
+            write!(f, " (rule {rule_id})")?;
+        }
+        write!(f, ": {}", self.reason)
+    }
 }
@@ -1,32 +1,232 @@
 use std::path::PathBuf;
 
-use fs_error::Error;
-use log::info;
-use matcher::{FsMatcher, GlobalFileScanFormat, GlobalFileScanRule};
+use fs_error::{Error, PolicyDiagnostic};
+use log::{error, info};
+use matcher::{FsMatcher, GlobalFileScanFormat, GlobalFileScanRule, Matcher};
+use model::{agent_model::DLPSensitiveFile, fs_model::RuleAction};
 
 pub mod fs_error;
 pub mod matcher;
 mod model;
+#[cfg(test)]
+mod test_utils;
 mod utils;
+pub mod validation;
+
+/// Verdict code when nothing hit or every hit is suppressed, other codes are those of the rule actions
+pub const VERDICT_NONE: i32 = 0;
+pub const VERDICT_AUDIT: i32 = RuleAction::Audit as i32;
+pub const VERDICT_ALERT: i32 = RuleAction::Alert as i32;
+pub const VERDICT_NOTIFY: i32 = RuleAction::Notify as i32;
+pub const VERDICT_ENCRYPT: i32 = RuleAction::Encrypt as i32;
+pub const VERDICT_QUARANTINE: i32 = RuleAction::Quarantine as i32;
+pub const VERDICT_BLOCK: i32 = RuleAction::Block as i32;
 
 const VERSION: &str = "165d4f07-f5e7-4dca-819c-8b0f7a440d1e";
 const DATE: &str = "2023.12.23";
 
-pub fn init_matcher(str_file_scan_rule: &str, str_file_scan_format: &str) {
+fn parse_config(
+    str_file_scan_rule: &str,
+    str_file_scan_format: &str,
+) -> Result<(GlobalFileScanRule, GlobalFileScanFormat), Error> {
     info!("[Version] Matcher lib version info: {DATE} (build: {VERSION})");
     info!("[Init] init matcher with RULE:\n{str_file_scan_rule}");
     info!("[Init] init matcher with FORMAT:\n{str_file_scan_format}");
-    let file_scan_rule =
-        serde_json::from_str::<GlobalFileScanRule>(str_file_scan_rule).unwrap_or_default();
-    let file_scan_format =
-        serde_json::from_str::<GlobalFileScanFormat>(str_file_scan_format).unwrap_or_default();
-    FsMatcher::init(file_scan_rule, file_scan_format)
+    let file_scan_rule = validation::load_file_scan_rule(str_file_scan_rule);
+    let file_scan_format = validation::load_file_scan_format(str_file_scan_format);
+    match (file_scan_rule, file_scan_format) {
+        (Ok(file_scan_rule), Ok(file_scan_format)) => Ok((file_scan_rule, file_scan_format)),
+        (file_scan_rule, file_scan_format) => {
+            let diagnostics = [file_scan_rule.err(), file_scan_format.err()]
+                .iter()
+                .flatten()
+                .flat_map(diagnostics_of)
+                .collect::<Vec<_>>();
+            let e = Error::Policy(diagnostics);
+            error!("[Init] Reject policy: {e}");
+            Err(e)
+        }
+    }
+}
+
+/// Init the default matcher used by [`match_rule`], or reload its policy.
+///
+/// An invalid policy is rejected and the previous one stays active.
+pub fn init_matcher(str_file_scan_rule: &str, str_file_scan_format: &str) -> Result<(), Error> {
+    let (file_scan_rule, file_scan_format) =
+        parse_config(str_file_scan_rule, str_file_scan_format)?;
+    FsMatcher::init(file_scan_rule, file_scan_format);
+    Ok(())
+}
+
+/// Create a standalone matcher, independent of the default one
+pub fn new_matcher(str_file_scan_rule: &str, str_file_scan_format: &str) -> Result<Matcher, Error> {
+    let (file_scan_rule, file_scan_format) =
+        parse_config(str_file_scan_rule, str_file_scan_format)?;
+    Ok(Matcher::new(file_scan_rule, file_scan_format))
+}
+
+/// Atomically replace the policy of a standalone matcher
+pub fn reload_matcher(
+    matcher: &Matcher,
+    str_file_scan_rule: &str,
+    str_file_scan_format: &str,
+) -> Result<(), Error> {
+    let (file_scan_rule, file_scan_format) =
+        parse_config(str_file_scan_rule, str_file_scan_format)?;
+    matcher.reload(file_scan_rule, file_scan_format);
+    Ok(())
+}
+
+/// `config_version` of the default matcher, empty if not initialized
+pub fn current_config_version() -> String {
+    FsMatcher::config_version().unwrap_or_default()
+}
+
+/// Policy diagnostics of a load result as a JSON array, empty on success
+pub fn diagnostics_json<T>(result: &Result<T, Error>) -> String {
+    let diagnostics = match result {
+        Ok(_) => Vec::new(),
+        Err(e) => diagnostics_of(e),
+    };
+    serde_json::to_string(&diagnostics).unwrap_or_default()
+}
+
+fn diagnostics_of(error: &Error) -> Vec<PolicyDiagnostic> {
+    match error {
+        Error::Policy(diagnostics) => diagnostics.clone(),
+        e => vec![PolicyDiagnostic {
+            document: "",
+            path: "$".to_owned(),
+            rule_id: None,
+            reason: e.to_string(),
+        }],
+    }
+}
+
+/// Suppress matching hits of the default matcher from now on, the
+/// suppression is a JSON object like the policy's `suppressions` entries
+pub fn add_suppression(str_suppression: &str) -> Result<(), Error> {
+    let suppression = validation::load_suppression(str_suppression)?;
+    FsMatcher::add_suppression(suppression).map_err(suppression_error)
+}
+
+/// [`add_suppression`] on a standalone matcher
+pub fn matcher_add_suppression(matcher: &Matcher, str_suppression: &str) -> Result<(), Error> {
+    let suppression = validation::load_suppression(str_suppression)?;
+    matcher
+        .add_suppression(suppression)
+        .map_err(suppression_error)
+}
+
+/// Drop the runtime suppressions of the default matcher with `id`, how many
+/// were dropped. Suppressions of the policy stay.
+pub fn remove_suppression(id: &str) -> usize {
+    FsMatcher::remove_suppression(id)
+}
+
+/// [`remove_suppression`] on a standalone matcher
+pub fn matcher_remove_suppression(matcher: &Matcher, id: &str) -> usize {
+    matcher.remove_suppression(id)
+}
+
+/// Runtime suppressions of the default matcher which have not expired, as a
+/// JSON array
+pub fn list_suppressions() -> String {
+    to_result_string(Some(FsMatcher::suppressions()))
+}
+
+/// [`list_suppressions`] on a standalone matcher
+pub fn matcher_list_suppressions(matcher: &Matcher) -> String {
+    to_result_string(Some(matcher.suppressions()))
+}
+
+fn suppression_error(reason: String) -> Error {
+    Error::Policy(vec![PolicyDiagnostic {
+        document: "suppression",
+        path: "$".to_owned(),
+        rule_id: None,
+        reason,
+    }])
+}
+
+/// Configure the file digest cache of a matcher, an empty `persist_path`
+/// keeps it in memory only
+pub fn set_hash_cache(matcher: &Matcher, capacity: usize, persist_path: &str) {
+    let persist_path = (!persist_path.is_empty()).then(|| PathBuf::from(persist_path));
+    matcher.set_hash_cache(capacity, persist_path)
+}
+
+/// Configure the file digest cache of the default matcher, an empty
+/// `persist_path` keeps it in memory only. The default matcher lives until
+/// the process exits, persist its cache with [`save_default_hash_cache`].
+pub fn set_default_hash_cache(capacity: usize, persist_path: &str) -> Result<(), Error> {
+    let persist_path = (!persist_path.is_empty()).then(|| PathBuf::from(persist_path));
+    FsMatcher::set_hash_cache(capacity, persist_path)
+}
+
+/// Persist the file digest cache of the default matcher
+pub fn save_default_hash_cache() -> Result<(), Error> {
+    FsMatcher::save_hash_cache()
 }
 
 pub fn match_rule(str_raw_result: &str, str_file_path: &str) -> String {
-    if let Some(result) =
-        FsMatcher::file_security_check(str_raw_result.to_owned(), &PathBuf::from(str_file_path))
-    {
+    to_result_string(FsMatcher::file_security_check(
+        str_raw_result.to_owned(),
+        &PathBuf::from(str_file_path),
+    ))
+}
+
+pub fn matcher_match(matcher: &Matcher, str_raw_result: &str, str_file_path: &str) -> String {
+    to_result_string(
+        matcher.file_security_check(str_raw_result.to_owned(), &PathBuf::from(str_file_path)),
+    )
+}
+
+/// [`match_rule`] plus the verdict code of the result
+pub fn match_rule_with_verdict(str_raw_result: &str, str_file_path: &str) -> (String, i32) {
+    to_verdict_result(FsMatcher::file_security_check(
+        str_raw_result.to_owned(),
+        &PathBuf::from(str_file_path),
+    ))
+}
+
+/// [`matcher_match`] plus the verdict code of the result
+pub fn matcher_match_with_verdict(
+    matcher: &Matcher,
+    str_raw_result: &str,
+    str_file_path: &str,
+) -> (String, i32) {
+    to_verdict_result(
+        matcher.file_security_check(str_raw_result.to_owned(), &PathBuf::from(str_file_path)),
+    )
+}
+
+/// Why each rule of the default matcher did or did not hit the file, as JSON
+pub fn explain_match(str_raw_result: &str, str_file_path: &str) -> String {
+    to_result_string(Some(FsMatcher::explain(
+        str_raw_result.to_owned(),
+        &PathBuf::from(str_file_path),
+    )))
+}
+
+/// Why each rule of `matcher` did or did not hit the file, as JSON
+pub fn matcher_explain(matcher: &Matcher, str_raw_result: &str, str_file_path: &str) -> String {
+    to_result_string(Some(
+        matcher.explain(str_raw_result.to_owned(), &PathBuf::from(str_file_path)),
+    ))
+}
+
+fn to_verdict_result(result: Option<DLPSensitiveFile>) -> (String, i32) {
+    let verdict = result
+        .as_ref()
+        .and_then(|result| result.verdict.as_ref())
+        .map_or(VERDICT_NONE, |verdict| verdict.action.code());
+    (to_result_string(result), verdict)
+}
+
+fn to_result_string<T: serde::Serialize>(result: Option<T>) -> String {
+    if let Some(result) = result {
         serde_json::to_string(&result).unwrap_or_default()
     } else {
         String::default()
@@ -1,30 +1,80 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock, RwLockWriteGuard},
 };
 
-use chrono::Utc;
-use evalexpr::{build_operator_tree, ContextWithMutableVariables};
+use chrono::{DateTime, Utc};
+use evalexpr::HashMapContext;
 use filesize::PathExt;
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 
+pub use self::clock::{Clock, FixedClock, SystemClock};
+use self::{
+    composite::match_composites,
+    context::LayeredContext,
+    explain::Rejection,
+    policy::{CompiledRule, PolicySnapshot},
+    registry::DocumentIndex,
+    scan_file::ScanFile,
+    suppression::{apply_suppressions, CompiledSuppression},
+};
 use crate::{
     fs_error::Error,
     model::{
-        agent_model::{DLPFileInfo, DLPFileSecurity, DLPSensitiveFile},
-        fs_model::{FileDigitalDictionary, FileScanRule},
-        raw_model::{RawScanResult, TRawScanResult},
+        agent_model::{
+            DLPEvidence, DLPFileInfo, DLPFileSecurity, DLPHitMember, DLPSensitiveFile,
+            DLPSuppressedHit,
+        },
+        explain_model::DLPMatchExplanation,
+        fs_model::{
+            CompositePolicy, DocumentRegistry, FileDigitalDictionary, FileScanRule, HashSettings,
+            RuleScope, SeveritySettings, Suppression, VerdictSettings,
+        },
+        raw_model::RawScanResult,
+    },
+    utils::{
+        common_utils::{system_time_to_unix_time, DigestAlgorithm},
+        hash_cache::HashCache,
     },
-    utils::common_utils::{md5_file, sha256_file, system_time_to_unix_time},
 };
 
+mod clock;
+mod composite;
+mod context;
+mod explain;
+mod policy;
+mod registry;
+mod scan_file;
+mod suppression;
+
 /// Global file security config
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct GlobalFileScanRule {
     pub config_version: String,
     pub file_scan_rules: Vec<FileScanRule>,
     pub file_digital_dictionary: HashMap<i32, FileDigitalDictionary>,
+    #[serde(default)]
+    pub hash_settings: HashSettings,
+    #[serde(default)]
+    pub document_registry: DocumentRegistry,
+    #[serde(default)]
+    pub severity: SeveritySettings,
+    #[serde(default)]
+    pub verdict: VerdictSettings,
+    #[serde(default)]
+    pub suppressions: Vec<Suppression>,
+    /// Policies over rule hits, evaluated after every rule
+    #[serde(default)]
+    pub composite_policies: Vec<CompositePolicy>,
+    /// Data items listed as evidence per hit at most, 0 lists none
+    #[serde(default = "default_evidence_limit")]
+    pub evidence_limit: usize,
+}
+
+fn default_evidence_limit() -> usize {
+    16
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -38,181 +88,523 @@ impl GlobalFileScanFormat {
     }
 }
 
-struct GlobalConfig {
-    file_scan_rule: GlobalFileScanRule,
-    file_scan_format: GlobalFileScanFormat,
+/// Owned matcher instance, holding one policy profile
+pub struct Matcher {
+    config: RwLock<Arc<PolicySnapshot>>,
+    hash_cache: Mutex<HashCache>,
+    clock: RwLock<Arc<dyn Clock>>,
+    /// Added at runtime, kept across policy reloads
+    suppressions: RwLock<Vec<CompiledSuppression>>,
 }
 
-static mut GLOBAL_CONFIG: Option<GlobalConfig> = None;
+static DEFAULT_MATCHER: OnceLock<Matcher> = OnceLock::new();
 
 #[repr(C)]
 pub struct FsMatcher {}
 
 impl FsMatcher {
+    /// Init the default matcher, or reload it if already initialized
     pub fn init(file_scan_rule: GlobalFileScanRule, file_scan_format: GlobalFileScanFormat) {
-        let global_config = GlobalConfig {
-            file_scan_rule,
-            file_scan_format,
+        let mut config = Some((file_scan_rule, file_scan_format));
+        let matcher = DEFAULT_MATCHER.get_or_init(|| {
+            let (file_scan_rule, file_scan_format) = config.take().unwrap_or_default();
+            Matcher::new(file_scan_rule, file_scan_format)
+        });
+        if let Some((file_scan_rule, file_scan_format)) = config {
+            matcher.reload(file_scan_rule, file_scan_format);
+        }
+    }
+
+    pub fn file_security_check(
+        raw_result_string: String,
+        matcher_file: &Path,
+    ) -> Option<DLPSensitiveFile> {
+        if let Some(matcher) = DEFAULT_MATCHER.get() {
+            matcher.file_security_check(raw_result_string, matcher_file)
+        } else {
+            error!("[SecurityCheck] Default matcher not init!");
+            None
+        }
+    }
+
+    pub fn explain(raw_result_string: String, matcher_file: &Path) -> DLPMatchExplanation {
+        if let Some(matcher) = DEFAULT_MATCHER.get() {
+            matcher.explain(raw_result_string, matcher_file)
+        } else {
+            DLPMatchExplanation {
+                path: matcher_file.to_string_lossy().to_string(),
+                error: "default matcher not init".to_owned(),
+                ..Default::default()
+            }
+        }
+    }
+
+    /// `config_version` of the policy currently loaded by the default matcher
+    pub fn config_version() -> Option<String> {
+        DEFAULT_MATCHER.get().map(Matcher::config_version)
+    }
+
+    /// Replace the file digest cache of the default matcher. The default
+    /// matcher is never dropped, persist the cache with [`Self::save_hash_cache`].
+    pub fn set_hash_cache(capacity: usize, persist_path: Option<PathBuf>) -> Result<(), Error> {
+        match DEFAULT_MATCHER.get() {
+            Some(matcher) => {
+                matcher.set_hash_cache(capacity, persist_path);
+                Ok(())
+            }
+            None => Err(Error::Scanner("default matcher not init".to_owned())),
+        }
+    }
+
+    pub fn save_hash_cache() -> Result<(), Error> {
+        match DEFAULT_MATCHER.get() {
+            Some(matcher) => matcher.save_hash_cache(),
+            None => Err(Error::Scanner("default matcher not init".to_owned())),
+        }
+    }
+
+    pub fn add_suppression(suppression: Suppression) -> Result<(), String> {
+        match DEFAULT_MATCHER.get() {
+            Some(matcher) => matcher.add_suppression(suppression),
+            None => Err("default matcher not init".to_owned()),
+        }
+    }
+
+    pub fn remove_suppression(id: &str) -> usize {
+        DEFAULT_MATCHER
+            .get()
+            .map_or(0, |matcher| matcher.remove_suppression(id))
+    }
+
+    pub fn suppressions() -> Vec<Suppression> {
+        DEFAULT_MATCHER
+            .get()
+            .map(Matcher::suppressions)
+            .unwrap_or_default()
+    }
+}
+
+impl Matcher {
+    pub fn new(file_scan_rule: GlobalFileScanRule, file_scan_format: GlobalFileScanFormat) -> Self {
+        Self {
+            config: RwLock::new(Arc::new(PolicySnapshot::new(
+                file_scan_rule,
+                file_scan_format,
+            ))),
+            hash_cache: Mutex::default(),
+            clock: RwLock::new(Arc::new(SystemClock)),
+            suppressions: RwLock::default(),
+        }
+    }
+
+    /// Replace the file digest cache, `persist_path` keeps it across restarts
+    pub fn set_hash_cache(&self, capacity: usize, persist_path: Option<PathBuf>) {
+        let hash_cache = match persist_path {
+            Some(persist_path) => HashCache::with_persist_path(capacity, persist_path),
+            None => HashCache::new(capacity),
         };
-        unsafe {
-            GLOBAL_CONFIG = Some(global_config);
+        let mut previous = std::mem::replace(&mut *self.lock_hash_cache(), hash_cache);
+        if let Err(e) = previous.save() {
+            warn!("[HashCache] Failed to save replaced cache: {e}");
         }
     }
 
+    /// Persist the file digest cache, no-op without a persist path
+    pub fn save_hash_cache(&self) -> Result<(), Error> {
+        self.lock_hash_cache().save()
+    }
+
+    /// Suppress matching hits from now on, on top of the policy's suppressions.
+    /// Expired runtime suppressions are dropped here.
+    pub fn add_suppression(&self, suppression: Suppression) -> Result<(), String> {
+        let suppression = CompiledSuppression::compile(suppression)?;
+        let now = self.now();
+        let mut suppressions = self.write_suppressions();
+        suppressions.retain(|suppression| !suppression.is_expired(now));
+        suppressions.push(suppression);
+        Ok(())
+    }
+
+    /// Drop the runtime suppressions with `id`, how many were dropped
+    pub fn remove_suppression(&self, id: &str) -> usize {
+        let mut suppressions = self.write_suppressions();
+        let count = suppressions.len();
+        suppressions.retain(|suppression| suppression.suppression().id != id);
+        count - suppressions.len()
+    }
+
+    /// Runtime suppressions which have not expired, in the order they were added
+    pub fn suppressions(&self) -> Vec<Suppression> {
+        let now = self.now();
+        let mut suppressions = self.write_suppressions();
+        suppressions.retain(|suppression| !suppression.is_expired(now));
+        suppressions
+            .iter()
+            .map(|suppression| suppression.suppression().to_owned())
+            .collect()
+    }
+
+    fn write_suppressions(&self) -> RwLockWriteGuard<'_, Vec<CompiledSuppression>> {
+        self.suppressions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replace the time source rule schedules are checked against
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.clock.write().unwrap_or_else(PoisonError::into_inner) = clock;
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.clock
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .now()
+    }
+
+    fn lock_hash_cache(&self) -> MutexGuard<'_, HashCache> {
+        self.hash_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Atomically swap in a new policy.
+    ///
+    /// Matches already in flight finish on the previous snapshot.
+    pub fn reload(
+        &self,
+        file_scan_rule: GlobalFileScanRule,
+        file_scan_format: GlobalFileScanFormat,
+    ) {
+        let config = Arc::new(PolicySnapshot::new(file_scan_rule, file_scan_format));
+        info!(
+            "[Reload] switch policy to version: {}",
+            config.config_version
+        );
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+
+    pub fn config_version(&self) -> String {
+        self.snapshot().config_version.clone()
+    }
+
+    fn snapshot(&self) -> Arc<PolicySnapshot> {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
     pub fn file_security_check(
+        &self,
         raw_result_string: String,
         matcher_file: &Path,
     ) -> Option<DLPSensitiveFile> {
         info!("[SecurityCheck] check file: {}", matcher_file.display());
-        if let Some(ref global_config) = unsafe { &GLOBAL_CONFIG } {
-            match serde_json::from_str::<RawScanResult>(&raw_result_string) {
-                Ok(raw_result) => {
-                    if raw_result.data.is_empty() {
-                        return None;
-                    }
-                    let mut hit_rules = HashSet::<DLPFileSecurity>::new();
-                    let scan_rule = &global_config.file_scan_rule;
-                    let file_scan_rule = scan_rule.file_scan_rules.clone();
-                    let file_digital_dictionary = scan_rule.file_digital_dictionary.clone();
-
-                    // check main data
-                    Self::match_rule(
-                        matcher_file,
-                        &file_scan_rule,
-                        &file_digital_dictionary,
-                        &raw_result,
-                        &mut hit_rules,
-                        &global_config.file_scan_format,
-                    );
+        let config = self.snapshot();
+        let scan_file = ScanFile::new(matcher_file, &self.hash_cache, &config, self.now());
+        match serde_json::from_str::<RawScanResult>(&raw_result_string) {
+            Ok(raw_result) => {
+                // registered documents hit even without sensitive data
+                let has_data = raw_result.has_data();
+                if !has_data && !config.registry.has_hits() {
+                    return None;
+                }
+                // by rule id, the first node producing a rule keeps the hit
+                let mut hit_rules = HashMap::<i32, DLPFileSecurity>::new();
 
-                    // check sub data
-                    if let Some(sub_data) = raw_result.sub_data {
-                        for raw_result in sub_data {
-                            // let file_scan_rule = file_scan_rule_cl.clone();
-                            Self::match_rule(
-                                matcher_file,
-                                &file_scan_rule,
-                                &file_digital_dictionary,
-                                &raw_result,
-                                &mut hit_rules,
-                                &global_config.file_scan_format,
-                            );
-                        }
+                if has_data {
+                    for (node, raw_node) in raw_result.nodes() {
+                        let member = Self::hit_member(&raw_result, node);
+                        // shared by every rule evaluated on this node
+                        let node_context =
+                            raw_node.update_context(HashMapContext::new(), &config.scoring);
+                        Self::match_rule(
+                            &config,
+                            &scan_file,
+                            raw_node,
+                            &node_context,
+                            RuleScope::Node,
+                            member.as_ref(),
+                            &mut hit_rules,
+                        );
                     }
 
-                    if hit_rules.is_empty() {
-                        None
-                    } else {
-                        let file_type = raw_result.format;
-                        let desc = raw_result.desc;
-                        let hit_rules = hit_rules.into_iter().collect::<Vec<DLPFileSecurity>>();
-                        match Self::update_file(
-                            matcher_file,
-                            desc,
-                            raw_result_string,
-                            file_type,
-                            hit_rules,
-                        ) {
-                            Ok(result) => Some(result),
-                            Err(e) => {
-                                error!("[SecurityCheck] Failed to update file info: {e}");
-                                None
-                            }
-                        }
+                    if config.has_container_rules {
+                        let container_context = raw_result
+                            .update_container_context(HashMapContext::new(), &config.scoring);
+                        Self::match_rule(
+                            &config,
+                            &scan_file,
+                            &raw_result,
+                            &container_context,
+                            RuleScope::Container,
+                            None,
+                            &mut hit_rules,
+                        );
                     }
                 }
-                Err(e) => {
-                    warn!("[SecurityCheck] Failed to parse raw scan result: {e}");
+
+                // check registered documents
+                Self::match_registry(&config.registry, &scan_file, &mut hit_rules);
+
+                let (hit_rules, suppressed) = self.settle_hits(&config, &scan_file, hit_rules);
+                if hit_rules.is_empty() && suppressed.is_empty() {
                     None
+                } else {
+                    let file_type = raw_result.format;
+                    let desc = raw_result.desc;
+                    match Self::update_file(
+                        &scan_file,
+                        &config,
+                        desc,
+                        raw_result_string,
+                        file_type,
+                        hit_rules,
+                        suppressed,
+                    ) {
+                        Ok(result) => Some(result),
+                        Err(e) => {
+                            error!("[SecurityCheck] Failed to update file info: {e}");
+                            None
+                        }
+                    }
                 }
             }
-        } else {
-            error!("[SecurityCheck] GLOBAL_CONFIG not init!");
-            None
+            Err(e) => {
+                warn!("[SecurityCheck] Failed to parse raw scan result: {e}");
+                None
+            }
         }
     }
 
+    /// Suppress hits, then combine the hits left into composite policies,
+    /// which may be suppressed in turn. The hits reported and the suppressed
+    /// ones, both sorted.
+    fn settle_hits(
+        &self,
+        config: &PolicySnapshot,
+        scan_file: &ScanFile,
+        hit_rules: HashMap<i32, DLPFileSecurity>,
+    ) -> (Vec<DLPFileSecurity>, Vec<DLPSuppressedHit>) {
+        let runtime_suppressions = self
+            .suppressions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let suppressions = config
+            .suppressions
+            .iter()
+            .chain(runtime_suppressions.iter());
+        let (kept, mut suppressed) =
+            apply_suppressions(suppressions.clone(), hit_rules.into_values(), scan_file);
+        let mut hit_rules = kept.into_iter().map(|hit| (hit.id, hit)).collect();
+        let composite_hits = match_composites(config, &mut hit_rules);
+        let (composite_hits, composite_suppressed) =
+            apply_suppressions(suppressions, composite_hits, scan_file);
+        drop(runtime_suppressions);
+        // component only hits are never reported, suppressed or not
+        suppressed.retain(|suppressed| !config.component_only.contains(&suppressed.hit.id));
+        suppressed.extend(composite_suppressed);
+
+        let mut hit_rules = hit_rules
+            .into_values()
+            .chain(composite_hits)
+            .collect::<Vec<_>>();
+        config.sort_hits(&mut hit_rules);
+        suppressed.sort_by(|a, b| config.cmp_hits(&a.hit, &b.hit));
+        (hit_rules, suppressed)
+    }
+
     fn match_rule(
-        matcher_file: &Path,
-        file_scan_rule: &Vec<FileScanRule>,
-        file_digital_dictionary: &HashMap<i32, FileDigitalDictionary>,
-        raw_result: &dyn TRawScanResult,
-        hit_rules: &mut HashSet<DLPFileSecurity>,
-        scan_format: &GlobalFileScanFormat,
+        config: &PolicySnapshot,
+        scan_file: &ScanFile,
+        raw_result: &RawScanResult,
+        node_context: &HashMapContext,
+        scope: RuleScope,
+        member: Option<&DLPHitMember>,
+        hit_rules: &mut HashMap<i32, DLPFileSecurity>,
     ) {
         let dlp_type = raw_result.get_dlp_type();
         let format = raw_result.get_format();
-        let encrypted = raw_result.need_check_encrypted();
-        let hidden = raw_result.need_check_hidden();
-
-        let file_size = matcher_file.size_on_disk().unwrap_or_default();
-        let match_types = scan_format.get_match_types(format);
 
-        for rule in file_scan_rule {
-            let file_size_range = rule.min_file_size..rule.max_file_size;
-            if rule.check_file_encrypted && !encrypted {
+        let file_size = scan_file.path.size_on_disk().unwrap_or_default();
+        // `file_types` is resolved by the index
+        for compiled_rule in config
+            .index
+            .candidates(dlp_type, &format)
+            .iter()
+            .map(|&i| &config.rules[i])
+        {
+            let rule = &compiled_rule.rule;
+            if rule.scope != scope {
                 continue;
             }
 
-            if rule.check_file_suffix && !hidden {
-                continue;
-            }
-
-            if !rule.file_types.is_empty()
-                && !rule.file_types.contains(&dlp_type)
-                && rule.file_types.intersection(&match_types).next().is_none()
-            {
-                continue;
-            }
-
-            if file_size == 0 || !file_size_range.contains(&file_size) {
-                continue;
-            }
-
-            if !rule.expr.is_empty() {
-                if let Ok(expression) = build_operator_tree(&rule.expr) {
-                    let mut context = rule.expr_context.to_owned();
-                    context = raw_result.update_context(context, file_digital_dictionary);
-                    if rule.md5_check {
-                        let file_md5 = md5_file(matcher_file).unwrap_or_default();
-                        let _ = context.set_value("md5".to_owned(), file_md5.into());
-                    }
-                    if let Ok(result) = expression.eval_with_context(&context) {
-                        if !result.as_boolean().unwrap_or_default() {
-                            continue;
-                        }
-                    } else {
-                        error!(
-                            "[Security ID:{}] Failed to evaluate expression on data: {}, context: {context:?}",
-                            rule.id, rule.expr
-                        );
-                        continue;
-                    }
-                } else {
+            match Self::check_rule(
+                compiled_rule,
+                scan_file,
+                raw_result,
+                node_context,
+                file_size,
+            ) {
+                Ok(()) => {}
+                Err(Rejection::ExprError(e)) => {
                     error!(
-                        "[Security ID:{}] Failed to build expression on data: {}",
+                        "[Security ID:{}] Failed to evaluate expression on data: {}, {e}",
                         rule.id, rule.expr
                     );
                     continue;
                 }
+                Err(_) => continue,
             }
 
-            let hit_rule = DLPFileSecurity {
+            let hit_rule = hit_rules.entry(rule.id).or_insert_with(|| DLPFileSecurity {
                 id: rule.id,
                 code: rule.code.to_owned(),
                 level: rule.level,
+                actions: rule.actions.to_owned(),
+                member: None,
+                members: Vec::new(),
+                evidence: Vec::new(),
+                components: Vec::new(),
+            });
+            if let Some(member) = member {
+                hit_rule.member.get_or_insert_with(|| member.clone());
+                hit_rule.members.push(member.clone());
+            }
+
+            // the node's own items, or every node's for a container rule
+            let nodes = match scope {
+                RuleScope::Node => {
+                    let node = member.map(|member| member.node.to_owned());
+                    vec![(node.unwrap_or_default(), raw_result)]
+                }
+                RuleScope::Container => raw_result.nodes(),
             };
-            hit_rules.insert(hit_rule);
+            for (node, raw_node) in nodes {
+                for item in &raw_node.data {
+                    if hit_rule.evidence.len() >= config.evidence_limit {
+                        break;
+                    }
+                    if compiled_rule.is_fed_by(item, &config.scoring) {
+                        hit_rule.evidence.push(DLPEvidence {
+                            id: item.id,
+                            location: item.location.to_owned(),
+                            length: item.length,
+                            offset: item.offset,
+                            node: node.to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the checks of one rule on one node in order, the error tells
+    /// which check rejected it. `file_types` and `scope` are up to the caller.
+    /// The path filter goes first, it holds for every node of the file.
+    fn check_rule(
+        compiled_rule: &CompiledRule,
+        scan_file: &ScanFile,
+        raw_result: &RawScanResult,
+        node_context: &HashMapContext,
+        file_size: u64,
+    ) -> Result<(), Rejection> {
+        let CompiledRule {
+            rule,
+            expr,
+            path_filter,
+            ..
+        } = compiled_rule;
+        compiled_rule
+            .active_window
+            .check(scan_file.now)
+            .map_err(Rejection::Inactive)?;
+
+        if !path_filter.is_empty() && !path_filter.allows(scan_file.facts()) {
+            return Err(Rejection::Path);
+        }
+
+        if rule.check_file_encrypted && !raw_result.need_check_encrypted() {
+            return Err(Rejection::Encrypted);
+        }
+
+        if rule.check_file_suffix && !raw_result.need_check_hidden() {
+            return Err(Rejection::Hidden);
+        }
+
+        let file_size_range = rule.min_file_size..rule.max_file_size;
+        if file_size == 0 || !file_size_range.contains(&file_size) {
+            return Err(Rejection::FileSize(file_size));
+        }
+
+        if compiled_rule.lacks_data(node_context) {
+            return Err(Rejection::NoData);
+        }
+
+        if let Some(expression) = expr {
+            let context = LayeredContext {
+                file: scan_file.file_context(rule.md5_check),
+                node: node_context,
+                rule: &rule.expr_context,
+            };
+            let result = expression
+                .eval_with_context(&context)
+                .map_err(Rejection::ExprError)?;
+            if !result.as_boolean().unwrap_or_default() {
+                return Err(Rejection::ExprFalse);
+            }
+        }
+        Ok(())
+    }
+
+    /// Member info of the node at `node`, none for the root
+    fn hit_member(root: &RawScanResult, node: Vec<usize>) -> Option<DLPHitMember> {
+        let lineage = root.lineage(&node);
+        let raw_member = lineage.last()?;
+        Some(DLPHitMember {
+            desc: raw_member.desc.to_owned(),
+            format: raw_member.format.to_owned(),
+            path: lineage.iter().map(|raw| raw.desc.to_owned()).collect(),
+            node,
+        })
+    }
+
+    fn match_registry(
+        registry: &DocumentIndex,
+        scan_file: &ScanFile,
+        hit_rules: &mut HashMap<i32, DLPFileSecurity>,
+    ) {
+        if !registry.has_hits() {
+            return;
+        }
+        let labels = scan_file.registry_labels();
+        for (label, hit) in registry.hits() {
+            if labels.contains(label) {
+                hit_rules.entry(hit.id).or_insert_with(|| DLPFileSecurity {
+                    id: hit.id,
+                    code: hit.code.to_owned(),
+                    level: hit.level,
+                    actions: hit.actions.to_owned(),
+                    member: None,
+                    members: Vec::new(),
+                    evidence: Vec::new(),
+                    components: Vec::new(),
+                });
+            }
         }
     }
 
     fn update_file(
-        file_path: &Path,
+        scan_file: &ScanFile,
+        config: &PolicySnapshot,
         desc: String,
         engine_result: String,
         file_type: String,
         hit_rules: Vec<DLPFileSecurity>,
+        suppressed: Vec<DLPSuppressedHit>,
     ) -> Result<DLPSensitiveFile, Error> {
+        let file_path = scan_file.path;
         if let Ok(md) = file_path.metadata() {
             let access_time = md.accessed().ok().map_or(0, system_time_to_unix_time) as u64;
             let update_time = md.modified().ok().map_or(0, system_time_to_unix_time) as u64;
@@ -225,8 +617,22 @@ impl FsMatcher {
                 .to_str()
                 .unwrap_or_default()
                 .to_owned();
-            let file_sha256 = sha256_file(&file_path_string).unwrap_or_default();
-            let file_md5 = md5_file(&file_path_string).unwrap_or_default();
+            let digests = scan_file.digests();
+            let file_sha256 = digests.get(DigestAlgorithm::Sha256).to_owned();
+            let file_md5 = digests.get(DigestAlgorithm::Md5).to_owned();
+            let file_hash_partial = digests.partial;
+            let file_fuzzy_hash = digests.get(DigestAlgorithm::Ssdeep).to_owned();
+            let file_hashes = config
+                .hash_settings
+                .algorithms
+                .iter()
+                .map(|algorithm| {
+                    (
+                        algorithm.name().to_owned(),
+                        digests.get(*algorithm).to_owned(),
+                    )
+                })
+                .collect();
 
             let file_info = DLPFileInfo {
                 file_name,
@@ -235,6 +641,9 @@ impl FsMatcher {
                 file_path: file_path_string,
                 file_sha256,
                 file_md5,
+                file_hash_partial,
+                file_hashes,
+                file_fuzzy_hash,
                 create_time,
                 update_time,
                 access_time,
@@ -245,9 +654,13 @@ impl FsMatcher {
 
             let result = DLPSensitiveFile {
                 file_info,
+                severity: config.file_severity(&hit_rules),
+                verdict: config.verdict(&hit_rules),
+                suppressed,
                 file_securities: hit_rules,
                 engine_result,
                 file_url,
+                config_version: config.config_version.to_owned(),
                 found_time: Utc::now().timestamp() as u64,
             };
             Ok(result)
@@ -259,3 +672,281 @@ impl FsMatcher {
         }
     }
 }
+
+impl Drop for Matcher {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_hash_cache() {
+            warn!("[HashCache] Failed to save cache: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::{FixedClock, GlobalFileScanFormat, Matcher};
+    use crate::{
+        model::{
+            agent_model::DLPFileSecurity,
+            fs_model::{FileScanRule, RuleScope},
+        },
+        test_utils::{logged_errors, policy, rule, temp_file},
+        utils::common_utils::DigestAlgorithm,
+    };
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_MD5: &str = "900150983cd24fb0d6963f7d28e17f72";
+    const ABC_SM3: &str = "66c7f0f462eeedd9d1f2d46bdc10e4e24167c4875cf2f7a2297da02b8f4ba8e0";
+
+    #[test]
+    fn test_picked_algorithms_keep_legacy_hashes() {
+        let path = temp_file("picked_algorithms", b"abc");
+        let mut file_scan_rule = policy(vec![rule(1, "body101 > 0")]);
+        file_scan_rule.hash_settings.algorithms = [DigestAlgorithm::Sm3].into();
+        let matcher = Matcher::new(file_scan_rule, GlobalFileScanFormat::default());
+        let raw =
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#;
+        let result = matcher.file_security_check(raw.to_owned(), &path);
+        std::fs::remove_file(&path).unwrap();
+
+        let file_info = result.unwrap().file_info;
+        assert_eq!(file_info.file_sha256, ABC_SHA256);
+        assert_eq!(file_info.file_md5, ABC_MD5);
+        assert_eq!(
+            file_info.file_hashes.into_iter().collect::<Vec<_>>(),
+            vec![("sm3".to_owned(), ABC_SM3.to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_function_rule_evidence() {
+        let path = temp_file("function_rule_evidence", b"abc");
+        let mut file_scan_rule = policy(vec![rule(
+            1,
+            r#"count(101) > 1 && count_in("header", 7) > 0 && score(900) > 0"#,
+        )]);
+        file_scan_rule.file_digital_dictionary =
+            serde_json::from_value(json!({"102": {"target_id": 900, "value": 1}})).unwrap();
+        let matcher = Matcher::new(file_scan_rule, GlobalFileScanFormat::default());
+        let raw = json!({"categoryId": 1, "format": "txt", "data": [
+            {"id": 101, "length": 3, "location": "body"},
+            {"id": 7, "length": 3, "location": "body"},
+            {"id": 101, "length": 3, "location": "body"},
+            {"id": 7, "length": 3, "location": "header"},
+            {"id": 55, "length": 3, "location": "body"},
+            {"id": 102, "length": 3, "location": "body"},
+        ]});
+        let result = matcher.file_security_check(raw.to_string(), &path);
+        std::fs::remove_file(&path).unwrap();
+
+        let hits = result.unwrap().file_securities;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            hits[0]
+                .evidence
+                .iter()
+                .map(|evidence| (evidence.id, evidence.location.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(101, "body"), (101, "body"), (7, "header"), (102, "body")]
+        );
+    }
+
+    /// A zip holding `mail.eml`, which holds `report.txt`, and `notes.txt`
+    const CONTAINER: &str = r#"{"categoryId":1,"format":"zip","desc":"a.zip","data":[],
+        "subFileData":[
+            {"categoryId":1,"format":"eml","desc":"mail.eml","data":[
+                {"id":101,"length":3,"location":"body"}
+            ],"subFileData":[
+                {"categoryId":1,"format":"txt","desc":"report.txt","data":[
+                    {"id":102,"length":4,"location":"body","offset":8}
+                ]}
+            ]},
+            {"categoryId":1,"format":"txt","desc":"notes.txt","data":[
+                {"id":101,"length":5,"location":"body"}
+            ]}
+        ]}"#;
+
+    /// Hits of `rules` on [`CONTAINER`], sorted by id
+    fn check_container(name: &str, rules: Vec<FileScanRule>) -> Vec<DLPFileSecurity> {
+        let path = temp_file(name, b"PK");
+        let matcher = Matcher::new(policy(rules), GlobalFileScanFormat::default());
+        let result = matcher.file_security_check(CONTAINER.to_owned(), &path);
+        std::fs::remove_file(&path).unwrap();
+        let mut hits = result
+            .map(|result| result.file_securities)
+            .unwrap_or_default();
+        hits.sort_by_key(|hit| hit.id);
+        hits
+    }
+
+    #[test]
+    fn test_nested_member() {
+        let hits = check_container("nested_member", vec![rule(1, "body102 > 0")]);
+        assert_eq!(hits.len(), 1);
+        let member = hits[0].member.as_ref().unwrap();
+        assert_eq!(member.desc, "report.txt");
+        assert_eq!(member.format, "txt");
+        assert_eq!(member.path, vec!["mail.eml", "report.txt"]);
+        assert_eq!(member.node, vec![0, 0]);
+        assert_eq!(hits[0].evidence.len(), 1);
+        assert_eq!(hits[0].evidence[0].node, vec![0, 0]);
+        assert_eq!(hits[0].evidence[0].offset, Some(8));
+    }
+
+    #[test]
+    fn test_member_and_members() {
+        let hits = check_container("member_and_members", vec![rule(1, "body101 > 0")]);
+        assert_eq!(hits.len(), 1);
+        // the first member keeps `member`, `members` lists every one
+        assert_eq!(hits[0].member.as_ref().unwrap().desc, "mail.eml");
+        assert_eq!(
+            hits[0]
+                .members
+                .iter()
+                .map(|member| (member.desc.as_str(), member.node.clone()))
+                .collect::<Vec<_>>(),
+            vec![("mail.eml", vec![0]), ("notes.txt", vec![1])]
+        );
+        assert_eq!(
+            hits[0]
+                .evidence
+                .iter()
+                .map(|evidence| (evidence.length, evidence.node.clone()))
+                .collect::<Vec<_>>(),
+            vec![(3, vec![0]), (5, vec![1])]
+        );
+    }
+
+    #[test]
+    fn test_container_scope() {
+        let expr = "body101 > 0 && body102 > 0";
+        let mut container_rule = rule(2, expr);
+        container_rule.scope = RuleScope::Container;
+        let hits = check_container("container_scope", vec![rule(1, expr), container_rule]);
+
+        // no single member holds both ids, the container does
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, 2);
+        assert!(hits[0].member.is_none());
+        assert!(hits[0].members.is_empty());
+        assert_eq!(
+            hits[0]
+                .evidence
+                .iter()
+                .map(|evidence| (evidence.id, evidence.node.clone()))
+                .collect::<Vec<_>>(),
+            vec![(101, vec![0]), (102, vec![0, 0]), (101, vec![1])]
+        );
+    }
+
+    #[test]
+    fn test_bad_rule_rejected_once() {
+        let mut bad_glob = rule(3, "body101 > 0");
+        bad_glob.path_filter.include.path_globs = vec!["a[".to_owned()];
+        let rules = vec![rule(1, "(body101 > 0"), rule(2, "body101 > 0"), bad_glob];
+        let (matcher, errors) =
+            logged_errors(|| Matcher::new(policy(rules), GlobalFileScanFormat::default()));
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(errors[0].starts_with("[Security ID:1] Reject rule"));
+        assert!(errors[1].starts_with("[Security ID:3] Reject rule"));
+
+        // dropped rules are neither matched nor reported again
+        let path = temp_file("bad_rule_rejected_once", b"abc");
+        let raw =
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#;
+        let (results, errors) =
+            logged_errors(|| [(); 2].map(|_| matcher.file_security_check(raw.to_owned(), &path)));
+        std::fs::remove_file(&path).unwrap();
+        assert!(errors.is_empty(), "{errors:?}");
+        for result in results {
+            let hits = result.unwrap().file_securities;
+            assert_eq!(hits.iter().map(|hit| hit.id).collect::<Vec<_>>(), vec![2]);
+        }
+    }
+
+    #[test]
+    fn test_runtime_suppressions() {
+        let matcher = Matcher::new(policy(Vec::new()), GlobalFileScanFormat::default());
+        matcher.set_clock(Arc::new(FixedClock(
+            "2026-01-01T00:00:00Z".parse().unwrap(),
+        )));
+        let add = |id: &str, expires: &str| {
+            let suppression = json!({
+                "id": id, "rule_ids": [1], "owner": "soc", "reason": "r", "expires": expires
+            });
+            matcher
+                .add_suppression(serde_json::from_value(suppression).unwrap())
+                .unwrap();
+        };
+        add("FP-1", "2026-02-01T00:00:00Z");
+        add("FP-2", "");
+        add("FP-1", "");
+        let ids = |matcher: &Matcher| {
+            let suppressions = matcher.suppressions();
+            suppressions
+                .into_iter()
+                .map(|suppression| suppression.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&matcher), ["FP-1", "FP-2", "FP-1"]);
+
+        // expired suppressions are dropped from the list, and on the next add
+        matcher.set_clock(Arc::new(FixedClock(
+            "2026-02-01T00:00:00Z".parse().unwrap(),
+        )));
+        assert_eq!(ids(&matcher), ["FP-2", "FP-1"]);
+        add("FP-3", "2026-01-15T00:00:00Z");
+        assert_eq!(ids(&matcher), ["FP-2", "FP-1"]);
+
+        assert_eq!(matcher.remove_suppression("FP-1"), 1);
+        assert_eq!(matcher.remove_suppression("FP-1"), 0);
+        assert_eq!(ids(&matcher), ["FP-2"]);
+    }
+
+    #[test]
+    fn test_composites_after_suppression() {
+        let path = temp_file("composites_after_suppression", b"abc");
+        let raw =
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#;
+        // hits kept and suppressed with `rule_ids` suppressed
+        let check = |rule_ids: &[i32]| {
+            let mut component = rule(1, "body101 > 0");
+            component.component_only = true;
+            let mut file_scan_rule = policy(vec![component, rule(2, "body101 > 0")]);
+            file_scan_rule.composite_policies = serde_json::from_value(json!([{
+                "id": 10, "code": "C10", "level": 3,
+                "condition": {"and": [{"rule": 1}, {"rule": 2}]}
+            }]))
+            .unwrap();
+            let matcher = Matcher::new(file_scan_rule, GlobalFileScanFormat::default());
+            let suppression = json!({"rule_ids": rule_ids, "owner": "soc", "reason": "r"});
+            matcher
+                .add_suppression(serde_json::from_value(suppression).unwrap())
+                .unwrap();
+            let result = matcher.file_security_check(raw.to_owned(), &path).unwrap();
+            (
+                result
+                    .file_securities
+                    .iter()
+                    .map(|hit| hit.id)
+                    .collect::<Vec<_>>(),
+                result
+                    .suppressed
+                    .iter()
+                    .map(|hit| hit.hit.id)
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(check(&[3]), (vec![10, 2], vec![]));
+        // a suppressed component no longer makes up the composite
+        assert_eq!(check(&[2]), (vec![], vec![2]));
+        // component only hits are not reported as suppressed either
+        assert_eq!(check(&[1]), (vec![2], vec![]));
+        assert_eq!(check(&[10]), (vec![2], vec![10]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
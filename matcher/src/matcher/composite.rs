@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use super::policy::PolicySnapshot;
+use crate::model::agent_model::DLPFileSecurity;
+
+/// Hits of the composite policies whose condition holds over `hit_rules`,
+/// the hits of component only rules are dropped from `hit_rules`
+pub(crate) fn match_composites(
+    config: &PolicySnapshot,
+    hit_rules: &mut HashMap<i32, DLPFileSecurity>,
+) -> Vec<DLPFileSecurity> {
+    let mut composite_hits = Vec::new();
+    for composite in &config.composites {
+        if !composite
+            .condition
+            .is_met(&|id| hit_rules.contains_key(&id))
+        {
+            continue;
+        }
+
+        let mut hit = DLPFileSecurity {
+            id: composite.id,
+            code: composite.code.to_owned(),
+            level: composite.level,
+            actions: composite.actions.to_owned(),
+            member: None,
+            members: Vec::new(),
+            evidence: Vec::new(),
+            components: Vec::new(),
+        };
+        // rules under a `not` count by not hitting, they have nothing to report
+        for (id, negated) in composite.condition.rule_ids() {
+            let Some(component) = hit_rules.get(&id).filter(|_| !negated) else {
+                continue;
+            };
+            if hit.components.contains(&id) {
+                continue;
+            }
+            hit.components.push(id);
+            for member in &component.members {
+                if !hit.members.contains(member) {
+                    hit.members.push(member.clone());
+                }
+            }
+            for evidence in &component.evidence {
+                if hit.evidence.len() < config.evidence_limit && !hit.evidence.contains(evidence) {
+                    hit.evidence.push(evidence.clone());
+                }
+            }
+        }
+        hit.member = hit.members.first().cloned();
+        composite_hits.push(hit);
+    }
+
+    hit_rules.retain(|id, _| !config.component_only.contains(id));
+    composite_hits
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use super::match_composites as composite_hits;
+    use crate::{
+        matcher::{policy::PolicySnapshot, GlobalFileScanFormat},
+        model::{
+            agent_model::{DLPEvidence, DLPFileSecurity},
+            fs_model::PolicyCondition,
+        },
+        test_utils::{hit, policy, rule},
+    };
+
+    fn condition(json: &str) -> PolicyCondition {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn is_met(condition: &PolicyCondition, hits: &[i32]) -> bool {
+        condition.is_met(&|id| hits.contains(&id))
+    }
+
+    /// Rules 1 to 3, 1 is component only
+    fn snapshot(composites: &str) -> PolicySnapshot {
+        let mut component = rule(1, "true");
+        component.component_only = true;
+        let mut file_scan_rule = policy(vec![component, rule(2, "true"), rule(3, "true")]);
+        file_scan_rule.composite_policies = serde_json::from_str(composites).unwrap();
+        PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default())
+    }
+
+    /// Hits of `ids`, each with one evidence item of id `100 + id`
+    fn hits(ids: &[i32]) -> HashMap<i32, DLPFileSecurity> {
+        ids.iter()
+            .map(|id| {
+                let mut hit = hit(*id, 1);
+                hit.evidence.push(DLPEvidence {
+                    id: 100 + id,
+                    location: "body".to_owned(),
+                    length: 1,
+                    offset: None,
+                    node: Vec::new(),
+                });
+                (*id, hit)
+            })
+            .collect()
+    }
+
+    fn ids(hits: &HashMap<i32, DLPFileSecurity>) -> BTreeSet<i32> {
+        hits.keys().copied().collect()
+    }
+
+    /// Replace `hit_rules` with what is reported
+    fn match_composites(config: &PolicySnapshot, hit_rules: &mut HashMap<i32, DLPFileSecurity>) {
+        let composite_hits = composite_hits(config, hit_rules);
+        hit_rules.extend(composite_hits.into_iter().map(|hit| (hit.id, hit)));
+    }
+
+    #[test]
+    fn test_and_or() {
+        let and = condition(r#"{"and":[{"rule":1},{"rule":2}]}"#);
+        assert!(is_met(&and, &[1, 2, 3]));
+        assert!(!is_met(&and, &[1, 3]));
+        assert!(is_met(&condition(r#"{"and":[]}"#), &[]));
+
+        let or = condition(r#"{"or":[{"rule":1},{"rule":2}]}"#);
+        assert!(is_met(&or, &[2]));
+        assert!(!is_met(&or, &[3]));
+        assert!(!is_met(&condition(r#"{"or":[]}"#), &[1]));
+    }
+
+    #[test]
+    fn test_not() {
+        let not = condition(r#"{"not":{"rule":1}}"#);
+        assert!(is_met(&not, &[]));
+        assert!(is_met(&not, &[2]));
+        assert!(!is_met(&not, &[1]));
+        assert_eq!(not.rule_ids(), vec![(1, true)]);
+
+        let nested = condition(r#"{"and":[{"rule":2},{"not":{"not":{"rule":1}}}]}"#);
+        assert!(is_met(&nested, &[1, 2]));
+        assert!(!is_met(&nested, &[2]));
+        assert_eq!(nested.rule_ids(), vec![(2, false), (1, false)]);
+    }
+
+    #[test]
+    fn test_k_of_n() {
+        let k_of_n = |k: usize| {
+            condition(&format!(
+                r#"{{"k_of_n":{{"k":{k},"of":[{{"rule":1}},{{"rule":2}},{{"rule":3}}]}}}}"#
+            ))
+        };
+        assert!(is_met(&k_of_n(0), &[]));
+        assert!(is_met(&k_of_n(1), &[3]));
+        assert!(!is_met(&k_of_n(1), &[]));
+        assert!(is_met(&k_of_n(2), &[1, 3]));
+        assert!(!is_met(&k_of_n(2), &[1]));
+        assert!(is_met(&k_of_n(3), &[1, 2, 3]));
+        assert!(!is_met(&k_of_n(3), &[1, 2]));
+        assert!(!is_met(&k_of_n(4), &[1, 2, 3]));
+    }
+
+    #[test]
+    fn test_match_composites() {
+        let config = snapshot(
+            r#"[
+                {"id":10,"code":"C10","level":3,"actions":["block"],
+                 "condition":{"and":[{"rule":1},{"rule":2}]}},
+                {"id":11,"code":"C11","level":2,"condition":{"not":{"rule":3}}}
+            ]"#,
+        );
+
+        let mut hit_rules = hits(&[1, 2]);
+        match_composites(&config, &mut hit_rules);
+        assert_eq!(ids(&hit_rules), BTreeSet::from([2, 10, 11]));
+        let composite = &hit_rules[&10];
+        assert_eq!(composite.code, "C10");
+        assert_eq!(composite.level, 3);
+        assert_eq!(composite.components, vec![1, 2]);
+        assert_eq!(
+            composite.evidence.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![101, 102]
+        );
+        // a `not` alone has no component to report
+        assert!(hit_rules[&11].components.is_empty());
+        assert!(hit_rules[&11].evidence.is_empty());
+
+        let mut hit_rules = hits(&[1, 3]);
+        match_composites(&config, &mut hit_rules);
+        assert_eq!(ids(&hit_rules), BTreeSet::from([3]));
+    }
+
+    #[test]
+    fn test_component_only_never_hits() {
+        let config = snapshot(
+            r#"[{"id":10,"code":"C10","level":3,"condition":{"or":[{"rule":1},{"rule":3}]}}]"#,
+        );
+        let mut hit_rules = hits(&[1]);
+        match_composites(&config, &mut hit_rules);
+        assert_eq!(ids(&hit_rules), BTreeSet::from([10]));
+        assert_eq!(hit_rules[&10].components, vec![1]);
+        assert_eq!(hit_rules[&10].evidence[0].id, 101);
+
+        let mut hit_rules = hits(&[1, 2, 3]);
+        match_composites(&config, &mut hit_rules);
+        assert_eq!(ids(&hit_rules), BTreeSet::from([2, 3, 10]));
+        assert_eq!(hit_rules[&10].components, vec![1, 3]);
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use evalexpr::{Context, EvalexprError, EvalexprResult, Function, HashMapContext, Value};
+
+use crate::utils::fuzzy_hash;
+
+/// Rule evaluation context, layering shared contexts instead of copying them.
+///
+/// Lookups go `file` -> `node` -> `rule`, the same precedence the values had
+/// when they were written into one context in that reverse order.
+#[derive(Debug)]
+pub(crate) struct LayeredContext<'a> {
+    /// Values and functions derived from the file itself, e.g. `md5`
+    pub file: &'a HashMapContext,
+    /// Scan data of the node under evaluation, and the builtin functions
+    pub node: &'a HashMapContext,
+    /// Defaults from the rule's `expr_context`
+    pub rule: &'a HashMapContext,
+}
+
+impl Context for LayeredContext<'_> {
+    fn get_value(&self, identifier: &str) -> Option<&Value> {
+        self.file
+            .get_value(identifier)
+            .or_else(|| self.node.get_value(identifier))
+            .or_else(|| self.rule.get_value(identifier))
+    }
+
+    fn call_function(&self, identifier: &str, argument: &Value) -> EvalexprResult<Value> {
+        match self.file.call_function(identifier, argument) {
+            Err(EvalexprError::FunctionIdentifierNotFound(_)) => {
+                self.node.call_function(identifier, argument)
+            }
+            result => result,
+        }
+    }
+
+    fn are_builtin_functions_disabled(&self) -> bool {
+        self.rule.are_builtin_functions_disabled()
+    }
+
+    fn set_builtin_functions_disabled(&mut self, _disabled: bool) -> EvalexprResult<()> {
+        Err(EvalexprError::ContextNotMutable)
+    }
+}
+
+/// `similar_to(hash, threshold)`: whether the file fuzzy hash scores at
+/// least `threshold` (0 - 100) against `hash`
+pub(crate) fn similar_to_function(file_fuzzy_hash: String) -> Function {
+    Function::new(move |argument| {
+        let arguments = argument.as_fixed_len_tuple(2)?;
+        let hash = arguments[0].as_string()?;
+        let threshold = arguments[1].as_int()?;
+        let score = if file_fuzzy_hash.is_empty() {
+            0
+        } else {
+            fuzzy_hash::compare(&file_fuzzy_hash, &hash)
+        };
+        Ok(Value::Boolean(score as i64 >= threshold))
+    })
+}
+
+/// `in_registry(label)`: whether the file is a registered document of `label`
+pub(crate) fn in_registry_function(labels: Arc<BTreeSet<String>>) -> Function {
+    Function::new(move |argument| {
+        let label = argument.as_string()?;
+        Ok(Value::Boolean(labels.contains(&label)))
+    })
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use evalexpr::{Context, EvalexprError, HashMapContext, Value};
+use filesize::PathExt;
+
+use super::{context::LayeredContext, policy::CompiledRule, scan_file::ScanFile, Matcher};
+use crate::model::{
+    agent_model::{DLPFileSecurity, DLPSuppressedHit},
+    explain_model::{
+        DLPMatchExplanation, DLPRuleCheck, DLPRuleExplanation, HitDisposition, HitKind, RuleStep,
+    },
+    fs_model::RuleScope,
+    raw_model::RawScanResult,
+    schedule::Inactive,
+};
+
+/// Check which rejected a rule on a node
+pub(crate) enum Rejection {
+    Inactive(Inactive),
+    FileTypes,
+    Path,
+    Encrypted,
+    Hidden,
+    FileSize(u64),
+    NoData,
+    ExprFalse,
+    ExprError(EvalexprError),
+}
+
+impl Rejection {
+    fn step(&self) -> RuleStep {
+        match self {
+            Rejection::Inactive(_) => RuleStep::Schedule,
+            Rejection::FileTypes => RuleStep::FileTypes,
+            Rejection::Path => RuleStep::Path,
+            Rejection::Encrypted => RuleStep::Encrypted,
+            Rejection::Hidden => RuleStep::Hidden,
+            Rejection::FileSize(_) => RuleStep::FileSize,
+            Rejection::NoData => RuleStep::Data,
+            Rejection::ExprFalse | Rejection::ExprError(_) => RuleStep::Expr,
+        }
+    }
+
+    fn reason(&self, compiled_rule: &CompiledRule) -> String {
+        let rule = &compiled_rule.rule;
+        match self {
+            Rejection::Inactive(inactive) => inactive.to_string(),
+            Rejection::FileTypes => "node type and format not in file_types".to_owned(),
+            Rejection::Path => "file path rejected by path_filter".to_owned(),
+            Rejection::Encrypted => "node is not encrypted".to_owned(),
+            Rejection::Hidden => "node is not hidden".to_owned(),
+            Rejection::FileSize(file_size) => format!(
+                "file size {file_size} not in [{}, {})",
+                rule.min_file_size, rule.max_file_size
+            ),
+            Rejection::NoData => "no expr variable comes from the node data".to_owned(),
+            Rejection::ExprFalse => "expr is false".to_owned(),
+            Rejection::ExprError(e) => format!("expr failed: {e}"),
+        }
+    }
+}
+
+/// A node as the rules see it
+struct ExplainedNode<'a> {
+    node: Vec<usize>,
+    raw_result: &'a RawScanResult,
+    scope: RuleScope,
+    context: HashMapContext,
+    candidates: &'a [usize],
+}
+
+impl Matcher {
+    /// Run every rule on every node like [`Matcher::file_security_check`],
+    /// recording the step which rejected or passed it
+    pub fn explain(&self, raw_result_string: String, matcher_file: &Path) -> DLPMatchExplanation {
+        let config = self.snapshot();
+        let mut explanation = DLPMatchExplanation {
+            path: matcher_file.to_string_lossy().to_string(),
+            config_version: config.config_version.to_owned(),
+            ..Default::default()
+        };
+        let raw_result = match serde_json::from_str::<RawScanResult>(&raw_result_string) {
+            Ok(raw_result) => raw_result,
+            Err(e) => {
+                explanation.error = format!("failed to parse raw scan result: {e}");
+                return explanation;
+            }
+        };
+        let scan_file = ScanFile::new(matcher_file, &self.hash_cache, &config, self.now());
+        let file_size = matcher_file.size_on_disk().unwrap_or_default();
+        let has_data = raw_result.has_data();
+
+        let mut nodes = raw_result
+            .nodes()
+            .into_iter()
+            .map(|(node, raw_node)| ExplainedNode {
+                node,
+                raw_result: raw_node,
+                scope: RuleScope::Node,
+                context: raw_node.update_context(HashMapContext::new(), &config.scoring),
+                candidates: config
+                    .index
+                    .candidates(raw_node.get_dlp_type(), &raw_node.get_format()),
+            })
+            .collect::<Vec<_>>();
+        if config.has_container_rules {
+            nodes.push(ExplainedNode {
+                node: Vec::new(),
+                raw_result: &raw_result,
+                scope: RuleScope::Container,
+                context: raw_result
+                    .update_container_context(HashMapContext::new(), &config.scoring),
+                candidates: config
+                    .index
+                    .candidates(raw_result.get_dlp_type(), &raw_result.get_format()),
+            });
+        }
+
+        let mut hit_rules = HashMap::new();
+        for (position, compiled_rule) in config.rules.iter().enumerate() {
+            let rule = &compiled_rule.rule;
+            let checks = nodes
+                .iter()
+                .filter(|node| node.scope == rule.scope)
+                .map(|node| {
+                    let outcome =
+                        if let Err(inactive) = compiled_rule.active_window.check(scan_file.now) {
+                            Err(Rejection::Inactive(inactive))
+                        } else if !has_data {
+                            Err(Rejection::NoData)
+                        } else if node.candidates.binary_search(&position).is_err() {
+                            Err(Rejection::FileTypes)
+                        } else {
+                            Self::check_rule(
+                                compiled_rule,
+                                &scan_file,
+                                node.raw_result,
+                                &node.context,
+                                file_size,
+                            )
+                        };
+                    Self::explain_check(compiled_rule, &scan_file, node, outcome)
+                })
+                .collect::<Vec<_>>();
+            let hit = checks.iter().any(|check| check.passed);
+            if hit {
+                hit_rules.insert(
+                    rule.id,
+                    DLPFileSecurity {
+                        id: rule.id,
+                        code: rule.code.to_owned(),
+                        level: rule.level,
+                        actions: rule.actions.to_owned(),
+                        member: None,
+                        members: Vec::new(),
+                        evidence: Vec::new(),
+                        components: Vec::new(),
+                    },
+                );
+            }
+            explanation.rules.push(DLPRuleExplanation {
+                id: rule.id,
+                code: rule.code.to_owned(),
+                kind: HitKind::Rule,
+                hit,
+                disposition: HitDisposition::Missed,
+                suppressed_by: String::new(),
+                components: Vec::new(),
+                checks,
+            });
+        }
+
+        // what became of the hits, as in `file_security_check`
+        if has_data || config.registry.has_hits() {
+            Self::match_registry(&config.registry, &scan_file, &mut hit_rules);
+        }
+        for (_, hit) in config.registry.hits() {
+            explanation
+                .rules
+                .push(Self::explain_entry(hit.id, &hit.code, HitKind::Registry));
+        }
+        for composite in &config.composites {
+            explanation.rules.push(Self::explain_entry(
+                composite.id,
+                &composite.code,
+                HitKind::Composite,
+            ));
+        }
+        let hit_ids = hit_rules.keys().copied().collect::<Vec<_>>();
+        let (reported, suppressed) = self.settle_hits(&config, &scan_file, hit_rules);
+        for entry in &mut explanation.rules {
+            entry.hit |= hit_ids.contains(&entry.id);
+            Self::explain_disposition(entry, &reported, &suppressed);
+        }
+        explanation
+    }
+
+    /// Entry of a registry or composite hit, without checks
+    fn explain_entry(id: i32, code: &str, kind: HitKind) -> DLPRuleExplanation {
+        DLPRuleExplanation {
+            id,
+            code: code.to_owned(),
+            kind,
+            hit: false,
+            disposition: HitDisposition::Missed,
+            suppressed_by: String::new(),
+            components: Vec::new(),
+            checks: Vec::new(),
+        }
+    }
+
+    fn explain_disposition(
+        entry: &mut DLPRuleExplanation,
+        reported: &[DLPFileSecurity],
+        suppressed: &[DLPSuppressedHit],
+    ) {
+        if let Some(hit) = reported.iter().find(|hit| hit.id == entry.id) {
+            entry.hit = true;
+            entry.disposition = HitDisposition::Reported;
+            entry.components = hit.components.to_owned();
+        } else if let Some(suppressed) = suppressed.iter().find(|hit| hit.hit.id == entry.id) {
+            entry.hit = true;
+            entry.disposition = HitDisposition::Suppressed;
+            entry.suppressed_by = suppressed.suppression.id.to_owned();
+            entry.components = suppressed.hit.components.to_owned();
+        } else if entry.hit {
+            // matched, neither reported nor suppressed: a component only rule
+            entry.disposition = HitDisposition::ComponentOnly;
+        }
+    }
+
+    fn explain_check(
+        compiled_rule: &CompiledRule,
+        scan_file: &ScanFile,
+        node: &ExplainedNode,
+        outcome: Result<(), Rejection>,
+    ) -> DLPRuleCheck {
+        let (step, passed, reason) = match &outcome {
+            Ok(()) if compiled_rule.expr.is_some() => (RuleStep::Expr, true, String::new()),
+            Ok(()) => (RuleStep::Data, true, String::new()),
+            Err(rejection) => (rejection.step(), false, rejection.reason(compiled_rule)),
+        };
+        let mut variables = BTreeMap::new();
+        if let (RuleStep::Expr, Some(expr)) = (step, &compiled_rule.expr) {
+            let context = LayeredContext {
+                file: scan_file.file_context(compiled_rule.rule.md5_check),
+                node: &node.context,
+                rule: &compiled_rule.rule.expr_context,
+            };
+            for variable in expr.iter_read_variable_identifiers() {
+                let value = context
+                    .get_value(variable)
+                    .map_or(serde_json::Value::Null, value_to_json);
+                variables.insert(variable.to_owned(), value);
+            }
+        }
+        DLPRuleCheck {
+            node: node.node.to_owned(),
+            desc: node.raw_result.desc.to_owned(),
+            scope: node.scope,
+            step,
+            passed,
+            reason,
+            variables,
+        }
+    }
+}
+
+fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::String(string) => string.to_owned().into(),
+        Value::Float(float) => (*float).into(),
+        Value::Int(int) => (*int).into(),
+        Value::Boolean(boolean) => (*boolean).into(),
+        Value::Tuple(tuple) => tuple.iter().map(value_to_json).collect(),
+        Value::Empty => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        matcher::{GlobalFileScanFormat, Matcher},
+        model::explain_model::{HitDisposition, HitKind},
+        test_utils::{policy, rule, temp_file},
+    };
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_dispositions() {
+        let mut component = rule(1, "body101 > 0");
+        component.component_only = true;
+        let mut file_scan_rule = policy(vec![
+            component,
+            rule(2, "body101 > 0"),
+            rule(3, "body102 > 0"),
+        ]);
+        file_scan_rule.document_registry = serde_json::from_value(json!({"sets": [{
+            "label": "contracts",
+            "sha256": [ABC_SHA256],
+            "hit": {"id": 70, "code": "REG", "level": 2}
+        }]}))
+        .unwrap();
+        file_scan_rule.composite_policies = serde_json::from_value(json!([{
+            "id": 10, "code": "C10", "level": 3,
+            "condition": {"and": [{"rule": 1}, {"rule": 2}]}
+        }]))
+        .unwrap();
+        file_scan_rule.suppressions = serde_json::from_value(json!([{
+            "id": "FP-70", "rule_ids": [70], "owner": "soc", "reason": "r"
+        }]))
+        .unwrap();
+        let matcher = Matcher::new(file_scan_rule, GlobalFileScanFormat::default());
+
+        let path = temp_file("explain_dispositions", b"abc");
+        let raw =
+            r#"{"categoryId":1,"format":"txt","data":[{"id":101,"length":3,"location":"body"}]}"#;
+        let explanation = matcher.explain(raw.to_owned(), &path);
+        std::fs::remove_file(&path).unwrap();
+
+        let entries = explanation
+            .rules
+            .iter()
+            .map(|entry| {
+                (
+                    entry.id,
+                    entry.kind,
+                    entry.hit,
+                    entry.disposition,
+                    entry.suppressed_by.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (1, HitKind::Rule, true, HitDisposition::ComponentOnly, ""),
+                (2, HitKind::Rule, true, HitDisposition::Reported, ""),
+                (3, HitKind::Rule, false, HitDisposition::Missed, ""),
+                (
+                    70,
+                    HitKind::Registry,
+                    true,
+                    HitDisposition::Suppressed,
+                    "FP-70"
+                ),
+                (10, HitKind::Composite, true, HitDisposition::Reported, ""),
+            ]
+        );
+        assert_eq!(explanation.rules[4].components, vec![1, 2]);
+        assert!(explanation.rules[3].checks.is_empty());
+    }
+}
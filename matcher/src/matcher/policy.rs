@@ -0,0 +1,777 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::Arc,
+};
+
+use evalexpr::{build_operator_tree, Context, Node, Operator, Value};
+use globset::{GlobSet, GlobSetBuilder};
+use log::error;
+use regex::RegexSet;
+
+use super::{
+    registry::{DocumentIndex, IN_REGISTRY_FUNCTION},
+    suppression::CompiledSuppression,
+    GlobalFileScanFormat, GlobalFileScanRule,
+};
+use crate::model::{
+    agent_model::{DLPFileSecurity, DLPFileSeverity, DLPVerdict},
+    fs_model::{
+        CompositePolicy, FileScanRule, HashSettings, PathFilter, PathPatterns, RuleAction,
+        RuleScope, SeveritySettings, SeverityTieBreak,
+    },
+    functions::{build_glob, FileFacts, FileGlobs, FILE_FUNCTIONS, NODE_FUNCTIONS, PATH_VARIABLES},
+    raw_model::{with_builtin_functions, DataStats, RawScanResultData},
+    schedule::ActiveWindow,
+    scoring::ScoringDictionary,
+};
+use crate::utils::common_utils::DigestAlgorithm;
+
+/// Variables which are not produced by scan data
+const NON_DATA_VARIABLES: [&str; 1] = ["md5"];
+/// `similar_to(hash, threshold)`, fuzzy hash similarity of the file
+pub(crate) const SIMILAR_TO_FUNCTION: &str = "similar_to";
+
+/// A rule with its expression parsed once at load time
+pub(crate) struct CompiledRule {
+    pub rule: FileScanRule,
+    /// `None` when the rule has no expression
+    pub expr: Option<Node>,
+    /// Variables of `expr` filled from scan data
+    pub data_variables: HashSet<String>,
+    /// Constant arguments of the node and glob functions in `expr`
+    pub function_arguments: FunctionArguments,
+    /// The expression can't be true unless one of `data_variables` comes
+    /// from scan data, so nodes without any of them are skipped up front
+    pub requires_data: bool,
+    /// `expr` calls [`SIMILAR_TO_FUNCTION`]
+    pub uses_fuzzy_hash: bool,
+    /// `expr` calls [`IN_REGISTRY_FUNCTION`]
+    pub uses_registry: bool,
+    /// `expr` calls one of [`FILE_FUNCTIONS`] or reads [`PATH_VARIABLES`]
+    pub uses_file_functions: bool,
+    pub path_filter: CompiledPathFilter,
+    /// `enabled`, validity and schedule of the rule
+    pub active_window: ActiveWindow,
+}
+
+impl CompiledRule {
+    pub fn compile(rule: FileScanRule) -> Result<Self, String> {
+        let expr = if rule.expr.is_empty() {
+            None
+        } else {
+            let expr = build_operator_tree(&rule.expr)
+                .map_err(|e| format!("failed to build expression: {}, {e}", rule.expr))?;
+            Some(expr)
+        };
+        let path_filter = CompiledPathFilter::new(&rule.path_filter)
+            .map_err(|e| format!("failed to build path_filter: {e}"))?;
+        let active_window =
+            ActiveWindow::new(&rule).map_err(|e| format!("failed to build schedule: {e}"))?;
+
+        let mut data_variables = HashSet::new();
+        let mut function_arguments = FunctionArguments::default();
+        let mut requires_data = false;
+        let mut uses_fuzzy_hash = false;
+        let mut uses_registry = false;
+        let mut uses_file_functions = false;
+        if let Some(ref expr) = expr {
+            function_arguments = FunctionArguments::new(expr);
+            // node functions read scan data too, but not through variables
+            let mut uses_node_functions = false;
+            for function in expr.iter_function_identifiers() {
+                uses_fuzzy_hash |= function == SIMILAR_TO_FUNCTION;
+                uses_registry |= function == IN_REGISTRY_FUNCTION;
+                uses_file_functions |= FILE_FUNCTIONS.contains(&function);
+                uses_node_functions |= NODE_FUNCTIONS.contains(&function);
+            }
+            let mut uses_non_data =
+                uses_fuzzy_hash || uses_registry || uses_file_functions || uses_node_functions;
+            for variable in expr.iter_read_variable_identifiers() {
+                if PATH_VARIABLES.contains(&variable) {
+                    uses_file_functions = true;
+                    uses_non_data = true;
+                } else if NON_DATA_VARIABLES.contains(&variable) {
+                    uses_non_data = true;
+                } else {
+                    data_variables.insert(variable.to_owned());
+                }
+            }
+            // with no scan data the context holds only `expr_context`
+            let context = with_builtin_functions(rule.expr_context.clone());
+            requires_data = !data_variables.is_empty()
+                && !uses_non_data
+                && !matches!(expr.eval_boolean_with_context(&context), Ok(true));
+        }
+
+        Ok(Self {
+            rule,
+            expr,
+            data_variables,
+            function_arguments,
+            requires_data,
+            uses_fuzzy_hash,
+            uses_registry,
+            uses_file_functions,
+            path_filter,
+            active_window,
+        })
+    }
+
+    /// Whether `item` feeds one of the expression variables or node
+    /// function arguments, directly or through a dictionary target
+    pub fn is_fed_by(&self, item: &RawScanResultData, scoring: &ScoringDictionary) -> bool {
+        let key = format!("{}{}", item.location, item.id);
+        if self.function_arguments.is_fed_by(item, scoring)
+            || self.data_variables.contains(&key)
+            || DataStats::SUFFIXES
+                .iter()
+                .any(|suffix| self.data_variables.contains(&format!("{key}{suffix}")))
+        {
+            return true;
+        }
+        scoring.targets_of(item.id).any(|target_id| {
+            self.data_variables
+                .contains(&format!("{}{target_id}", item.location))
+                || self
+                    .data_variables
+                    .contains(&format!("score_{}_{target_id}", item.location))
+        })
+    }
+
+    /// Whether the node context carries none of the data this rule needs
+    pub fn lacks_data<C: Context>(&self, node_context: &C) -> bool {
+        self.requires_data
+            && !self
+                .data_variables
+                .iter()
+                .any(|variable| node_context.get_value(variable).is_some())
+    }
+}
+
+/// Constant ids and targets passed to the node functions, arguments computed
+/// at run time are not known up front and feed no evidence. Also the constant
+/// globs of `path_matches` and `name_matches`.
+#[derive(Default)]
+pub(crate) struct FunctionArguments {
+    /// `count`, `any_of` and `all_of` ids
+    ids: HashSet<i64>,
+    /// `count_in` locations and ids
+    located_ids: HashSet<(String, i64)>,
+    /// `score` targets
+    targets: HashSet<i64>,
+    /// `path_matches` and `name_matches` globs
+    pub globs: HashSet<String>,
+}
+
+impl FunctionArguments {
+    fn new(expr: &Node) -> Self {
+        let mut arguments = Self::default();
+        for node in expr.iter() {
+            let Operator::FunctionIdentifier { identifier } = node.operator() else {
+                continue;
+            };
+            // a constant, or a tuple of them, anything else is computed
+            let Some(constants) = node
+                .children()
+                .iter()
+                .flat_map(Node::iter)
+                .filter_map(|node| match node.operator() {
+                    Operator::Const { value } => Some(Some(value)),
+                    Operator::RootNode | Operator::Tuple => None,
+                    _ => Some(None),
+                })
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let ids = constants.iter().filter_map(|value| match value {
+                Value::Int(id) => Some(*id),
+                _ => None,
+            });
+            match identifier.as_str() {
+                "count" | "any_of" | "all_of" => arguments.ids.extend(ids),
+                "score" => arguments.targets.extend(ids),
+                "count_in" => {
+                    if let [Value::String(location), Value::Int(id)] = constants[..] {
+                        arguments.located_ids.insert((location.to_owned(), *id));
+                    }
+                }
+                "path_matches" | "name_matches" => {
+                    if let [Value::String(glob)] = constants[..] {
+                        arguments.globs.insert(glob.to_owned());
+                    }
+                }
+                _ => {}
+            }
+        }
+        arguments
+    }
+
+    fn is_fed_by(&self, item: &RawScanResultData, scoring: &ScoringDictionary) -> bool {
+        let id = item.id as i64;
+        self.ids.contains(&id)
+            || self.located_ids.contains(&(item.location.to_owned(), id))
+            || scoring
+                .targets_of(item.id)
+                .any(|target_id| self.targets.contains(&(target_id as i64)))
+    }
+}
+
+/// Compiled [`PathPatterns`], matched against the scanned file
+struct CompiledPatterns {
+    path_globs: GlobSet,
+    path_regex: RegexSet,
+    name_globs: GlobSet,
+    name_regex: RegexSet,
+    extensions: HashSet<String>,
+}
+
+impl CompiledPatterns {
+    fn new(patterns: &PathPatterns) -> Result<Option<Self>, String> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            path_globs: build_glob_set(&patterns.path_globs)?,
+            path_regex: RegexSet::new(&patterns.path_regex).map_err(|e| e.to_string())?,
+            name_globs: build_glob_set(&patterns.name_globs)?,
+            name_regex: RegexSet::new(&patterns.name_regex).map_err(|e| e.to_string())?,
+            extensions: patterns
+                .extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+        }))
+    }
+
+    fn is_match(&self, facts: &FileFacts) -> bool {
+        let path = facts.path.to_string_lossy();
+        let name = facts.name();
+        self.path_globs.is_match(&facts.path)
+            || self.path_regex.is_match(&path)
+            || self.name_globs.is_match(&name)
+            || self.name_regex.is_match(&name)
+            || self.extensions.contains(&facts.ext())
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(build_glob(glob).map_err(|e| e.to_string())?);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Compiled [`PathFilter`]
+pub(crate) struct CompiledPathFilter {
+    include: Option<CompiledPatterns>,
+    exclude: Option<CompiledPatterns>,
+}
+
+impl CompiledPathFilter {
+    fn new(path_filter: &PathFilter) -> Result<Self, String> {
+        Ok(Self {
+            include: CompiledPatterns::new(&path_filter.include)?,
+            exclude: CompiledPatterns::new(&path_filter.exclude)?,
+        })
+    }
+
+    pub fn allows(&self, facts: &FileFacts) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(facts))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(facts))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none()
+    }
+}
+
+/// Candidate rules by dlp type and format key, as indices into the rule list.
+/// Every list is merged and sorted up front, lookups don't allocate.
+#[derive(Default)]
+pub(crate) struct RuleIndex {
+    /// Rules without `file_types`, candidates for every node
+    any_type: Vec<usize>,
+    /// Candidates of a dlp type, under a format key matching no type
+    by_type: HashMap<i32, Vec<usize>>,
+    by_format: HashMap<String, FormatCandidates>,
+}
+
+/// Candidates of a format key
+struct FormatCandidates {
+    /// For a dlp type without rules of its own
+    positions: Vec<usize>,
+    by_type: HashMap<i32, Vec<usize>>,
+}
+
+impl RuleIndex {
+    fn new(rules: &[CompiledRule], file_scan_format: &GlobalFileScanFormat) -> Self {
+        let mut any_type = Vec::new();
+        let mut typed = HashMap::<i32, Vec<usize>>::new();
+        for (position, CompiledRule { rule, .. }) in rules.iter().enumerate() {
+            if rule.file_types.is_empty() {
+                any_type.push(position);
+            }
+            for file_type in &rule.file_types {
+                typed.entry(*file_type).or_default().push(position);
+            }
+        }
+
+        let by_type = typed
+            .iter()
+            .map(|(dlp_type, positions)| (*dlp_type, merge(&[&any_type, positions])))
+            .collect();
+        let by_format = file_scan_format
+            .format
+            .iter()
+            .map(|(format_key, match_types)| {
+                let format_positions = match_types
+                    .iter()
+                    .filter_map(|match_type| typed.get(match_type))
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>();
+                let by_type = typed
+                    .iter()
+                    .map(|(dlp_type, positions)| {
+                        (*dlp_type, merge(&[&any_type, &format_positions, positions]))
+                    })
+                    .collect();
+                let positions = merge(&[&any_type, &format_positions]);
+                (
+                    format_key.to_owned(),
+                    FormatCandidates { positions, by_type },
+                )
+            })
+            .collect();
+        Self {
+            any_type,
+            by_type,
+            by_format,
+        }
+    }
+
+    /// Rules which may apply to a node, in policy order
+    pub fn candidates(&self, dlp_type: i32, format_key: &str) -> &[usize] {
+        match self.by_format.get(format_key) {
+            Some(format) => format.by_type.get(&dlp_type).unwrap_or(&format.positions),
+            None => self.by_type.get(&dlp_type).unwrap_or(&self.any_type),
+        }
+    }
+}
+
+/// Sorted union of rule positions
+fn merge(lists: &[&[usize]]) -> Vec<usize> {
+    let mut positions = lists.concat();
+    positions.sort_unstable();
+    positions.dedup();
+    positions
+}
+
+/// Policy snapshot, replaced as a whole on reload
+pub(crate) struct PolicySnapshot {
+    pub config_version: String,
+    pub rules: Vec<CompiledRule>,
+    pub index: RuleIndex,
+    pub scoring: ScoringDictionary,
+    pub hash_settings: HashSettings,
+    /// Digests computed for each file, the policy's choice plus what rules need
+    pub algorithms: BTreeSet<DigestAlgorithm>,
+    pub registry: DocumentIndex,
+    pub evidence_limit: usize,
+    /// Some rule is evaluated with [`RuleScope::Container`]
+    pub has_container_rules: bool,
+    /// Some rule calls [`SIMILAR_TO_FUNCTION`]
+    pub uses_fuzzy_hash: bool,
+    /// Some rule calls [`IN_REGISTRY_FUNCTION`]
+    pub uses_registry: bool,
+    /// Some rule calls one of [`FILE_FUNCTIONS`] or reads [`PATH_VARIABLES`]
+    pub uses_file_functions: bool,
+    /// Constant globs of every rule, broken ones fail when evaluated
+    pub file_globs: Arc<FileGlobs>,
+    pub severity: SeveritySettings,
+    /// Rule id -> position in `file_scan_rules`
+    rule_positions: HashMap<i32, usize>,
+    /// Every action, strongest first
+    precedence: Vec<RuleAction>,
+    pub suppressions: Vec<CompiledSuppression>,
+    pub composites: Vec<CompositePolicy>,
+    /// Rules whose hits only feed `composites`
+    pub component_only: HashSet<i32>,
+}
+
+impl PolicySnapshot {
+    /// Compile every rule, rules with a broken expression are dropped here
+    /// rather than failing on each match
+    pub fn new(file_scan_rule: GlobalFileScanRule, file_scan_format: GlobalFileScanFormat) -> Self {
+        let rules = file_scan_rule
+            .file_scan_rules
+            .into_iter()
+            .filter_map(|rule| {
+                let id = rule.id;
+                CompiledRule::compile(rule)
+                    .map_err(|e| error!("[Security ID:{id}] Reject rule, {e}"))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        let index = RuleIndex::new(&rules, &file_scan_format);
+        let composites = file_scan_rule.composite_policies;
+        // composite policies rank after the rules
+        let rule_positions = rules
+            .iter()
+            .map(|rule| rule.rule.id)
+            .chain(composites.iter().map(|composite| composite.id))
+            .enumerate()
+            .map(|(position, id)| (id, position))
+            .collect();
+        let component_only = rules
+            .iter()
+            .filter(|rule| rule.rule.component_only)
+            .map(|rule| rule.rule.id)
+            .collect();
+        let suppressions = file_scan_rule
+            .suppressions
+            .into_iter()
+            .filter_map(|suppression| {
+                let id = suppression.id.to_owned();
+                CompiledSuppression::compile(suppression)
+                    .map_err(|e| error!("[Suppression {id}] Reject suppression, {e}"))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        let registry = DocumentIndex::new(file_scan_rule.document_registry);
+        let uses_fuzzy_hash = rules.iter().any(|rule| rule.uses_fuzzy_hash);
+        let uses_registry = rules.iter().any(|rule| rule.uses_registry);
+        let uses_file_functions = rules.iter().any(|rule| rule.uses_file_functions);
+        let mut algorithms = file_scan_rule.hash_settings.all_algorithms();
+        if uses_fuzzy_hash {
+            algorithms.insert(DigestAlgorithm::Ssdeep);
+        }
+        let has_container_rules = rules
+            .iter()
+            .any(|rule| rule.rule.scope == RuleScope::Container);
+        let file_globs = rules
+            .iter()
+            .flat_map(|rule| &rule.function_arguments.globs)
+            .filter_map(|glob| {
+                let matcher = build_glob(glob).ok()?.compile_matcher();
+                Some((glob.to_owned(), matcher))
+            })
+            .collect();
+        Self {
+            config_version: file_scan_rule.config_version,
+            rules,
+            index,
+            scoring: ScoringDictionary::new(&file_scan_rule.file_digital_dictionary),
+            hash_settings: file_scan_rule.hash_settings,
+            algorithms,
+            registry,
+            has_container_rules,
+            uses_fuzzy_hash,
+            uses_registry,
+            uses_file_functions,
+            file_globs: Arc::new(file_globs),
+            evidence_limit: file_scan_rule.evidence_limit,
+            severity: file_scan_rule.severity,
+            rule_positions,
+            precedence: file_scan_rule.verdict.full_precedence(),
+            suppressions,
+            composites,
+            component_only,
+        }
+    }
+
+    /// Sort hits by level, highest first, then by id
+    pub fn sort_hits(&self, hits: &mut [DLPFileSecurity]) {
+        hits.sort_by(|a, b| self.cmp_hits(a, b));
+    }
+
+    /// Order of [`Self::sort_hits`]
+    pub fn cmp_hits(&self, a: &DLPFileSecurity, b: &DLPFileSecurity) -> Ordering {
+        b.level.cmp(&a.level).then(a.id.cmp(&b.id))
+    }
+
+    /// Highest level among `hits`, ties broken per [`SeveritySettings`],
+    /// none without hits
+    pub fn file_severity(&self, hits: &[DLPFileSecurity]) -> Option<DLPFileSeverity> {
+        let level = hits.iter().map(|hit| hit.level).max();
+        let top = hits.iter().filter(|hit| Some(hit.level) == level);
+        let top = match self.severity.tie_break {
+            SeverityTieBreak::LowestId => top.min_by_key(|hit| hit.id),
+            SeverityTieBreak::HighestId => top.max_by_key(|hit| hit.id),
+            SeverityTieBreak::PolicyOrder => top.min_by_key(|hit| {
+                let position = self.rule_positions.get(&hit.id).copied();
+                (position.unwrap_or(usize::MAX), hit.id)
+            }),
+        };
+        top.map(|hit| DLPFileSeverity {
+            level: hit.level,
+            id: hit.id,
+            code: hit.code.to_owned(),
+        })
+    }
+
+    /// Strongest action any of `hits` asks for, `hits` come sorted
+    pub fn verdict(&self, hits: &[DLPFileSecurity]) -> Option<DLPVerdict> {
+        let actions_of = |hit: &DLPFileSecurity| {
+            if hit.actions.is_empty() {
+                vec![RuleAction::Audit]
+            } else {
+                hit.actions.to_owned()
+            }
+        };
+        let actions = self
+            .precedence
+            .iter()
+            .copied()
+            .filter(|action| hits.iter().any(|hit| actions_of(hit).contains(action)))
+            .collect::<Vec<_>>();
+        let action = actions.first().copied()?;
+        let hit = hits.iter().find(|hit| actions_of(hit).contains(&action))?;
+        Some(DLPVerdict {
+            action,
+            id: hit.id,
+            code: hit.code.to_owned(),
+            actions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, HashSet},
+        path::PathBuf,
+        time::SystemTime,
+    };
+
+    use serde_json::json;
+
+    use evalexpr::{ContextWithMutableVariables, HashMapContext};
+
+    use super::{CompiledPathFilter, CompiledRule, PolicySnapshot};
+    use crate::{
+        matcher::GlobalFileScanFormat,
+        model::{fs_model::SeverityTieBreak, functions::FileFacts},
+        test_utils::{hit, policy, rule},
+    };
+
+    /// Rules listed in the order 5, 2, 9
+    fn snapshot(tie_break: SeverityTieBreak) -> PolicySnapshot {
+        let mut file_scan_rule = policy(vec![rule(5, "true"), rule(2, "true"), rule(9, "true")]);
+        file_scan_rule.severity.tie_break = tie_break;
+        PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default())
+    }
+
+    #[test]
+    fn test_sort_hits() {
+        let mut hits = vec![hit(9, 2), hit(1, 1), hit(5, 3), hit(2, 2), hit(7, 1)];
+        snapshot(SeverityTieBreak::LowestId).sort_hits(&mut hits);
+        assert_eq!(
+            hits.iter()
+                .map(|hit| (hit.id, hit.level))
+                .collect::<Vec<_>>(),
+            vec![(5, 3), (2, 2), (9, 2), (1, 1), (7, 1)]
+        );
+    }
+
+    #[test]
+    fn test_severity_tie_break() {
+        // 1 is not a rule, e.g. a registry hit, it comes after every rule
+        let hits = vec![hit(9, 2), hit(1, 2), hit(5, 2), hit(2, 2), hit(7, 1)];
+        for (tie_break, id) in [
+            (SeverityTieBreak::LowestId, 1),
+            (SeverityTieBreak::HighestId, 9),
+            (SeverityTieBreak::PolicyOrder, 5),
+        ] {
+            let severity = snapshot(tie_break).file_severity(&hits).unwrap();
+            assert_eq!((severity.level, severity.id), (2, id), "{tie_break:?}");
+            assert_eq!(severity.code, format!("R{id}"));
+        }
+
+        let severity = snapshot(SeverityTieBreak::PolicyOrder)
+            .file_severity(&hits[..2])
+            .unwrap();
+        assert_eq!((severity.level, severity.id), (2, 9));
+        assert!(snapshot(SeverityTieBreak::PolicyOrder)
+            .file_severity(&[])
+            .is_none());
+    }
+
+    #[test]
+    fn test_file_globs() {
+        let file_scan_rule = policy(vec![
+            rule(1, r#"path_matches("/data/**") && name_matches("*.xlsx")"#),
+            rule(
+                2,
+                r#"path_matches("/data/**") || path_matches(file_dir + "/*")"#,
+            ),
+            rule(3, r#"name_matches("[")"#),
+        ]);
+        let snapshot = PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default());
+        let mut globs = snapshot.file_globs.keys().collect::<Vec<_>>();
+        globs.sort();
+        assert_eq!(globs, vec!["*.xlsx", "/data/**"]);
+        assert!(snapshot.file_globs["/data/**"].is_match("/data/q4/a.xlsx"));
+    }
+
+    fn path_filter(path_filter: serde_json::Value) -> CompiledPathFilter {
+        CompiledPathFilter::new(&serde_json::from_value(path_filter).unwrap()).unwrap()
+    }
+
+    /// Which of `paths` `path_filter` allows
+    fn allowed<'a>(path_filter: &CompiledPathFilter, paths: &[&'a str]) -> Vec<&'a str> {
+        paths
+            .iter()
+            .copied()
+            .filter(|path| {
+                path_filter.allows(&FileFacts {
+                    path: PathBuf::from(path),
+                    size: 0,
+                    modified: None,
+                    now: SystemTime::now(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exclude_over_include() {
+        let filter = path_filter(json!({
+            "include": {"path_globs": ["/data/**"]},
+            "exclude": {"name_globs": ["*.tmp"], "path_regex": ["/private/"]}
+        }));
+        assert!(!filter.is_empty());
+        assert_eq!(
+            allowed(
+                &filter,
+                &[
+                    "/data/a.txt",
+                    "/data/a.tmp",
+                    "/data/private/a.txt",
+                    "/home/a.txt"
+                ]
+            ),
+            vec!["/data/a.txt"]
+        );
+
+        let exclude_only = path_filter(json!({"exclude": {"extensions": ["tmp"]}}));
+        assert_eq!(
+            allowed(&exclude_only, &["/data/a.txt", "/home/a.tmp"]),
+            vec!["/data/a.txt"]
+        );
+
+        let empty = path_filter(json!({}));
+        assert!(empty.is_empty());
+        assert_eq!(allowed(&empty, &["/data/a.tmp"]), vec!["/data/a.tmp"]);
+    }
+
+    #[test]
+    fn test_literal_separator() {
+        let one_level = path_filter(json!({"include": {"path_globs": ["/data/*.txt"]}}));
+        assert_eq!(
+            allowed(&one_level, &["/data/a.txt", "/data/q4/a.txt"]),
+            vec!["/data/a.txt"]
+        );
+
+        let any_level = path_filter(json!({"include": {"path_globs": ["/data/**/*.txt"]}}));
+        assert_eq!(
+            allowed(
+                &any_level,
+                &["/data/a.txt", "/data/q4/a.txt", "/home/a.txt"]
+            ),
+            vec!["/data/a.txt", "/data/q4/a.txt"]
+        );
+
+        // name globs only see the file name
+        let name = path_filter(json!({"include": {"name_globs": ["a*"]}}));
+        assert_eq!(
+            allowed(&name, &["/data/a.txt", "/a/b.txt"]),
+            vec!["/data/a.txt"]
+        );
+    }
+
+    #[test]
+    fn test_extension_case() {
+        let filter = path_filter(json!({"include": {"extensions": [".XLSX", "pdf"]}}));
+        assert_eq!(
+            allowed(
+                &filter,
+                &["/a/b.xlsx", "/a/b.PDF", "/a/b.Xlsx", "/a/b.doc", "/a/xlsx"]
+            ),
+            vec!["/a/b.xlsx", "/a/b.PDF", "/a/b.Xlsx"]
+        );
+
+        // unlike extensions, globs are case sensitive
+        let glob = path_filter(json!({"include": {"name_globs": ["*.pdf"]}}));
+        assert_eq!(allowed(&glob, &["/a/b.pdf", "/a/b.PDF"]), vec!["/a/b.pdf"]);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let typed = |id: i32, file_types: &[i32]| {
+            let mut rule = rule(id, "true");
+            rule.file_types = file_types.iter().copied().collect();
+            rule
+        };
+        let rules = vec![
+            typed(0, &[]),
+            typed(1, &[10]),
+            typed(2, &[20]),
+            typed(3, &[10, 30]),
+        ];
+        let format = GlobalFileScanFormat {
+            format: HashMap::from([
+                ("docx".to_owned(), HashSet::from([20])),
+                ("zip".to_owned(), HashSet::from([40])),
+            ]),
+        };
+        let index = PolicySnapshot::new(policy(rules), format).index;
+
+        // by dlp type
+        assert_eq!(index.candidates(10, "txt"), [0, 1, 3]);
+        assert_eq!(index.candidates(30, "txt"), [0, 3]);
+        // by format key, on top of the dlp type
+        assert_eq!(index.candidates(99, "docx"), [0, 2]);
+        assert_eq!(index.candidates(10, "docx"), [0, 1, 2, 3]);
+        assert_eq!(index.candidates(20, "docx"), [0, 2]);
+        // neither matches a rule
+        assert_eq!(index.candidates(99, "zip"), [0]);
+        assert_eq!(index.candidates(99, "txt"), [0]);
+    }
+
+    #[test]
+    fn test_lacks_data() {
+        let compile = |expr: &str| CompiledRule::compile(rule(1, expr)).unwrap();
+        let mut node_context = HashMapContext::new();
+        node_context
+            .set_value("body102".to_owned(), 1.into())
+            .unwrap();
+
+        let needs_data = compile("body101 > 0 || header101_count > 1");
+        assert!(needs_data.lacks_data(&node_context));
+        node_context
+            .set_value("header101_count".to_owned(), 2.into())
+            .unwrap();
+        assert!(!needs_data.lacks_data(&node_context));
+
+        // may hold without scan data, node functions read it in other ways
+        let empty = HashMapContext::new();
+        let mut defaulted = rule(1, "body101 == 0");
+        defaulted
+            .expr_context
+            .set_value("body101".to_owned(), 0.into())
+            .unwrap();
+        assert!(!CompiledRule::compile(defaulted).unwrap().lacks_data(&empty));
+        assert!(!compile("count(101) > 0").lacks_data(&empty));
+        assert!(!compile(r#"body101 > 0 || name_matches("*.txt")"#).lacks_data(&empty));
+        assert!(!compile("").lacks_data(&empty));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeSet, HashSet};
+
+use log::{error, warn};
+
+use crate::{
+    model::fs_model::{DocumentRegistry, RegistryHit},
+    utils::common_utils::{DigestAlgorithm, FileDigests},
+};
+
+/// `in_registry(label)`, whether the file is a registered document of `label`
+pub(crate) const IN_REGISTRY_FUNCTION: &str = "in_registry";
+
+struct DocumentSet {
+    label: String,
+    sha256: HashSet<[u8; 32]>,
+    md5: HashSet<[u8; 16]>,
+    hit: Option<RegistryHit>,
+}
+
+impl DocumentSet {
+    fn contains(&self, sha256: Option<[u8; 32]>, md5: Option<[u8; 16]>) -> bool {
+        sha256.is_some_and(|digest| self.sha256.contains(&digest))
+            || md5.is_some_and(|digest| self.md5.contains(&digest))
+    }
+}
+
+/// Registered document digests, decoded into fixed size keys for lookup
+#[derive(Default)]
+pub(crate) struct DocumentIndex {
+    sets: Vec<DocumentSet>,
+}
+
+impl DocumentIndex {
+    pub fn new(mut registry: DocumentRegistry) -> Self {
+        for (_, file, e) in registry.load_files() {
+            error!("[Registry] Failed to load {file}: {e}");
+        }
+
+        let sets = registry
+            .sets
+            .into_iter()
+            .map(|set| DocumentSet {
+                sha256: decode_digests(&set.label, &set.sha256),
+                md5: decode_digests(&set.label, &set.md5),
+                label: set.label,
+                hit: set.hit,
+            })
+            .collect();
+        Self { sets }
+    }
+
+    /// Labels of every set the file belongs to
+    pub fn labels_of(&self, digests: &FileDigests) -> BTreeSet<String> {
+        let sha256 = decode_digest(digests.get(DigestAlgorithm::Sha256));
+        let md5 = decode_digest(digests.get(DigestAlgorithm::Md5));
+        self.sets
+            .iter()
+            .filter(|set| set.contains(sha256, md5))
+            .map(|set| set.label.to_owned())
+            .collect()
+    }
+
+    /// Sets which raise hits of their own
+    pub fn hits(&self) -> impl Iterator<Item = (&str, &RegistryHit)> {
+        self.sets
+            .iter()
+            .filter_map(|set| set.hit.as_ref().map(|hit| (set.label.as_str(), hit)))
+    }
+
+    pub fn has_hits(&self) -> bool {
+        self.hits().next().is_some()
+    }
+}
+
+fn decode_digest<const N: usize>(digest: &str) -> Option<[u8; N]> {
+    let mut bytes = [0u8; N];
+    hex::decode_to_slice(digest.trim(), &mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn decode_digests<const N: usize>(label: &str, digests: &[String]) -> HashSet<[u8; N]> {
+    digests
+        .iter()
+        .filter_map(|digest| {
+            let decoded = decode_digest(digest);
+            if decoded.is_none() {
+                warn!("[Registry] Skip malformed digest in {label}: {digest}");
+            }
+            decoded
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::DocumentIndex;
+    use crate::{
+        matcher::{GlobalFileScanFormat, Matcher},
+        model::fs_model::{DocumentRegistry, RegisteredDocumentSet, RegistryHit},
+        test_utils::{policy, temp_file},
+        utils::common_utils::{digest_file_partial, DigestAlgorithm, FileDigests},
+    };
+
+    // digests of "abc"
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_MD5: &str = "900150983cd24fb0d6963f7d28e17f72";
+
+    fn registry(hit: Option<RegistryHit>) -> DocumentRegistry {
+        DocumentRegistry {
+            files: Vec::new(),
+            sets: vec![
+                RegisteredDocumentSet {
+                    label: "contracts".to_owned(),
+                    sha256: vec![ABC_SHA256.to_uppercase()],
+                    md5: Vec::new(),
+                    hit,
+                },
+                RegisteredDocumentSet {
+                    label: "designs".to_owned(),
+                    sha256: vec!["not hex".to_owned()],
+                    md5: vec![ABC_MD5.to_owned()],
+                    hit: None,
+                },
+            ],
+        }
+    }
+
+    fn digests(sha256: &str, md5: &str) -> FileDigests {
+        FileDigests {
+            digests: BTreeMap::from([
+                (DigestAlgorithm::Sha256, sha256.to_owned()),
+                (DigestAlgorithm::Md5, md5.to_owned()),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let index = DocumentIndex::new(registry(None));
+        assert!(!index.has_hits());
+
+        let labels = index.labels_of(&digests(ABC_SHA256, ABC_MD5));
+        assert_eq!(
+            labels.into_iter().collect::<Vec<_>>(),
+            vec!["contracts", "designs"]
+        );
+        let labels = index.labels_of(&digests("", ABC_MD5));
+        assert_eq!(labels.into_iter().collect::<Vec<_>>(), vec!["designs"]);
+        assert!(index.labels_of(&digests(ABC_MD5, "")).is_empty());
+        assert!(index.labels_of(&FileDigests::default()).is_empty());
+    }
+
+    #[test]
+    fn test_hit_without_scan_data() {
+        let path = temp_file("registry_hit", b"abc");
+        let mut file_scan_rule = policy(Vec::new());
+        file_scan_rule.document_registry = registry(Some(RegistryHit {
+            id: 70,
+            code: "REG".to_owned(),
+            level: 2,
+            actions: Vec::new(),
+        }));
+        let matcher = Matcher::new(file_scan_rule, GlobalFileScanFormat::default());
+        let raw = r#"{"categoryId":1,"format":"txt","desc":"abc.txt","data":[]}"#;
+        let result = matcher.file_security_check(raw.to_owned(), &path);
+
+        let other = temp_file("registry_miss", b"abd");
+        let missed = matcher.file_security_check(raw.to_owned(), &other);
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&other).unwrap();
+
+        let result = result.unwrap();
+        assert_eq!(result.file_securities.len(), 1);
+        assert_eq!(result.file_securities[0].id, 70);
+        assert_eq!(result.file_securities[0].code, "REG");
+        assert!(missed.is_none());
+    }
+
+    #[test]
+    fn test_partial_digest_not_looked_up() {
+        let path = temp_file("registry_partial", b"abcdef");
+        let partial = digest_file_partial(&path, &DigestAlgorithm::DEFAULT.into(), 1).unwrap();
+        let hit = RegistryHit {
+            id: 70,
+            code: "REG".to_owned(),
+            level: 2,
+            actions: Vec::new(),
+        };
+        let mut file_scan_rule = policy(Vec::new());
+        file_scan_rule.document_registry.sets = vec![RegisteredDocumentSet {
+            label: "partial".to_owned(),
+            sha256: vec![partial.get(DigestAlgorithm::Sha256).to_owned()],
+            md5: vec![partial.get(DigestAlgorithm::Md5).to_owned()],
+            hit: Some(hit),
+        }];
+        file_scan_rule.hash_settings.max_hash_size = 2;
+        file_scan_rule.hash_settings.partial_hash = true;
+        file_scan_rule.hash_settings.partial_chunk_size = 1;
+        let matcher = Matcher::new(file_scan_rule, GlobalFileScanFormat::default());
+        let raw = r#"{"categoryId":1,"format":"txt","desc":"abcdef.txt","data":[]}"#;
+        let result = matcher.file_security_check(raw.to_owned(), &path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_none());
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    cell::OnceCell,
+    collections::BTreeSet,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use evalexpr::{ContextWithMutableFunctions, ContextWithMutableVariables, HashMapContext};
+use filesize::PathExt;
+use log::{info, warn};
+
+use super::{
+    context::{in_registry_function, similar_to_function},
+    policy::{PolicySnapshot, SIMILAR_TO_FUNCTION},
+    registry::IN_REGISTRY_FUNCTION,
+};
+use crate::{
+    model::{
+        fs_model::HashMode,
+        functions::{set_file_functions, set_path_variables, FileFacts},
+    },
+    utils::{
+        common_utils::{digest_file, digest_file_partial, DigestAlgorithm, FileDigests},
+        hash_cache::{FileKey, HashCache},
+    },
+};
+
+/// The file under check, its digests are computed at most once per check
+pub(crate) struct ScanFile<'a> {
+    pub path: &'a Path,
+    hash_cache: &'a Mutex<HashCache>,
+    config: &'a PolicySnapshot,
+    digests: OnceCell<FileDigests>,
+    registry_labels: OnceCell<Arc<BTreeSet<String>>>,
+    facts: OnceCell<FileFacts>,
+    file_context: OnceCell<HashMapContext>,
+    md5_file_context: OnceCell<HashMapContext>,
+    /// Clock time of the check, rule schedules are checked against it
+    pub now: DateTime<Utc>,
+}
+
+impl<'a> ScanFile<'a> {
+    pub fn new(
+        path: &'a Path,
+        hash_cache: &'a Mutex<HashCache>,
+        config: &'a PolicySnapshot,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            path,
+            hash_cache,
+            config,
+            digests: OnceCell::new(),
+            registry_labels: OnceCell::new(),
+            facts: OnceCell::new(),
+            file_context: OnceCell::new(),
+            md5_file_context: OnceCell::new(),
+            now,
+        }
+    }
+
+    pub fn digests(&self) -> &FileDigests {
+        self.digests.get_or_init(|| self.load_digests())
+    }
+
+    /// Labels of the registered document sets holding this file, none
+    /// without a full file digest
+    pub fn registry_labels(&self) -> Arc<BTreeSet<String>> {
+        self.registry_labels
+            .get_or_init(|| {
+                let digests = self.digests();
+                if digests.partial || digests.get(DigestAlgorithm::Sha256).is_empty() {
+                    warn!(
+                        "[Registry] No full digest of {}, skip registry lookup",
+                        self.path.display()
+                    );
+                    return Arc::default();
+                }
+                Arc::new(self.config.registry.labels_of(digests))
+            })
+            .clone()
+    }
+
+    /// What the file functions of rule expressions see
+    pub fn facts(&self) -> &FileFacts {
+        self.facts.get_or_init(|| FileFacts {
+            path: self.path.to_owned(),
+            size: self.path.size_on_disk().unwrap_or_default(),
+            modified: self.path.metadata().and_then(|md| md.modified()).ok(),
+            now: SystemTime::from(self.now),
+        })
+    }
+
+    /// Per file variables and functions of rule expressions, built once per
+    /// check with what any rule of the policy needs. `md5` is only set for
+    /// rules with `md5_check`.
+    pub fn file_context(&self, md5_check: bool) -> &HashMapContext {
+        let file_context = self.file_context.get_or_init(|| self.build_file_context());
+        if !md5_check {
+            return file_context;
+        }
+        self.md5_file_context.get_or_init(|| {
+            let mut md5_file_context = file_context.clone();
+            let file_md5 = self.digests().get(DigestAlgorithm::Md5).to_owned();
+            let _ = md5_file_context.set_value("md5".to_owned(), file_md5.into());
+            md5_file_context
+        })
+    }
+
+    fn build_file_context(&self) -> HashMapContext {
+        let mut file_context = HashMapContext::new();
+        if self.config.uses_fuzzy_hash {
+            let file_fuzzy_hash = self.digests().get(DigestAlgorithm::Ssdeep).to_owned();
+            let _ = file_context.set_function(
+                SIMILAR_TO_FUNCTION.to_owned(),
+                similar_to_function(file_fuzzy_hash),
+            );
+        }
+        if self.config.uses_registry {
+            let _ = file_context.set_function(
+                IN_REGISTRY_FUNCTION.to_owned(),
+                in_registry_function(self.registry_labels()),
+            );
+        }
+        if self.config.uses_file_functions {
+            set_file_functions(&mut file_context, self.facts(), &self.config.file_globs);
+            set_path_variables(&mut file_context, self.facts());
+        }
+        file_context
+    }
+
+    fn load_digests(&self) -> FileDigests {
+        let md = match self.path.metadata() {
+            Ok(md) => md,
+            Err(e) => {
+                warn!("[Hash] Failed to get meta of {}: {e}", self.path.display());
+                return FileDigests::default();
+            }
+        };
+
+        let mode = self.config.hash_settings.mode_for(md.len());
+        let chunk_size = match mode {
+            HashMode::Partial(chunk_size) => Some(chunk_size),
+            _ => None,
+        };
+        let partial = chunk_size.is_some();
+        let mut algorithms = self.config.algorithms.clone();
+        if partial {
+            algorithms.remove(&DigestAlgorithm::Ssdeep);
+        }
+        let key = FileKey::new(self.path, &md);
+        if let Some(digests) = self.lock_cache().get(&key) {
+            // a changed chunk size leaves stale partial digests behind
+            if digests.partial == partial
+                && digests.partial_chunk_size == chunk_size.unwrap_or_default()
+                && digests.contains_all(&algorithms)
+            {
+                return digests.clone();
+            }
+        }
+
+        // hash outside the lock, other checks keep using the cache meanwhile
+        let digests = match mode {
+            HashMode::Full => digest_file(self.path, &algorithms),
+            HashMode::Partial(chunk_size) => {
+                digest_file_partial(self.path, &algorithms, chunk_size)
+            }
+            HashMode::Skip => {
+                info!(
+                    "[Hash] Skip hashing {}, {} bytes over max_hash_size",
+                    self.path.display(),
+                    md.len()
+                );
+                return FileDigests::default();
+            }
+        };
+        match digests {
+            Ok(digests) => {
+                self.lock_cache().insert(key, digests.clone());
+                digests
+            }
+            Err(e) => {
+                warn!("[Hash] Failed to hash {}: {e}", self.path.display());
+                FileDigests::default()
+            }
+        }
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, HashCache> {
+        self.hash_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::Utc;
+    use evalexpr::{Context, Value};
+
+    use super::ScanFile;
+    use crate::{
+        matcher::{policy::PolicySnapshot, GlobalFileScanFormat, GlobalFileScanRule},
+        model::fs_model::HashSettings,
+        test_utils::{policy, rule, temp_file},
+        utils::common_utils::{digest_file, digest_file_partial, DigestAlgorithm, FileDigests},
+    };
+
+    #[test]
+    fn test_hash_modes() {
+        let path = temp_file("hash_modes", b"0123456789");
+        let full = digest_file(&path, &DigestAlgorithm::DEFAULT.into()).unwrap();
+        let digests = |max_hash_size: u64, partial_hash: bool| -> FileDigests {
+            let file_scan_rule = GlobalFileScanRule {
+                hash_settings: HashSettings {
+                    max_hash_size,
+                    partial_hash,
+                    partial_chunk_size: 3,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let config = PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default());
+            let hash_cache = Mutex::default();
+            ScanFile::new(&path, &hash_cache, &config, Utc::now())
+                .digests()
+                .clone()
+        };
+
+        assert_eq!(digests(0, true), full);
+        assert_eq!(digests(10, true), full);
+        let partial = digests(9, true);
+        assert!(partial.partial);
+        assert_eq!(
+            partial.get(DigestAlgorithm::Sha256),
+            "3729e43a667db0a968c1236248e7b1846c16f4446bb07e7a7dcef230606ce426"
+        );
+        let skipped = digests(9, false);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(skipped, FileDigests::default());
+    }
+
+    #[test]
+    fn test_chunk_size_change() {
+        let path = temp_file("chunk_size_change", b"0123456789");
+        let hash_cache = Mutex::default();
+        let digests = |partial_chunk_size: u64| -> FileDigests {
+            let file_scan_rule = GlobalFileScanRule {
+                hash_settings: HashSettings {
+                    max_hash_size: 9,
+                    partial_hash: true,
+                    partial_chunk_size,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let config = PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default());
+            ScanFile::new(&path, &hash_cache, &config, Utc::now())
+                .digests()
+                .clone()
+        };
+
+        assert_eq!(digests(3).partial_chunk_size, 3);
+        let rehashed = digests(4);
+        let expected = digest_file_partial(&path, &DigestAlgorithm::DEFAULT.into(), 4).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(rehashed, expected);
+    }
+
+    #[test]
+    fn test_file_context_built_once() {
+        let path = temp_file("file_context_built_once", b"abc");
+        let file_scan_rule = policy(vec![rule(1, r#"path_matches("/tmp/**")"#)]);
+        let config = PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default());
+        let hash_cache = Mutex::default();
+        let scan_file = ScanFile::new(&path, &hash_cache, &config, Utc::now());
+
+        let file_context = scan_file.file_context(false);
+        assert!(std::ptr::eq(file_context, scan_file.file_context(false)));
+        assert!(file_context.get_value("md5").is_none());
+        assert_eq!(
+            file_context.get_value("file_path"),
+            Some(&Value::String(path.to_string_lossy().to_string()))
+        );
+
+        let md5_file_context = scan_file.file_context(true);
+        assert!(std::ptr::eq(md5_file_context, scan_file.file_context(true)));
+        assert_eq!(
+            md5_file_context.get_value("md5"),
+            Some(&Value::String(
+                "900150983cd24fb0d6963f7d28e17f72".to_owned()
+            ))
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
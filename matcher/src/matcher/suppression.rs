@@ -0,0 +1,221 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use globset::GlobMatcher;
+use log::warn;
+
+use super::scan_file::ScanFile;
+use crate::{
+    model::{
+        agent_model::{DLPFileSecurity, DLPSuppressedHit, DLPSuppression},
+        fs_model::Suppression,
+        functions::build_glob,
+        schedule::parse_time,
+    },
+    utils::common_utils::DigestAlgorithm,
+};
+
+/// A suppression with its glob and expiry parsed once
+pub(crate) struct CompiledSuppression {
+    suppression: Suppression,
+    path_glob: Option<GlobMatcher>,
+    expires: Option<DateTime<FixedOffset>>,
+}
+
+impl CompiledSuppression {
+    pub fn compile(suppression: Suppression) -> Result<Self, String> {
+        let path_glob = if suppression.path_glob.is_empty() {
+            None
+        } else {
+            let glob = build_glob(&suppression.path_glob)
+                .map_err(|e| format!("bad glob {}: {e}", suppression.path_glob))?;
+            Some(glob.compile_matcher())
+        };
+        Ok(Self {
+            path_glob,
+            expires: parse_time(&suppression.expires)?,
+            suppression,
+        })
+    }
+
+    pub fn suppression(&self) -> &Suppression {
+        &self.suppression
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| now >= expires)
+    }
+
+    /// Whether `hit` on the file is covered, the file is hashed only when
+    /// every other criterion matches
+    fn covers(&self, hit: &DLPFileSecurity, scan_file: &ScanFile) -> bool {
+        let Suppression {
+            sha256, rule_ids, ..
+        } = &self.suppression;
+        !self.is_expired(scan_file.now)
+            && (rule_ids.is_empty() || rule_ids.contains(&hit.id))
+            && self
+                .path_glob
+                .as_ref()
+                .is_none_or(|glob| glob.is_match(scan_file.path))
+            && (sha256.is_empty() || self.matches_sha256(sha256, scan_file))
+    }
+
+    /// Only a full file digest is compared, a partial one or none at all
+    /// never matches
+    fn matches_sha256(&self, sha256: &str, scan_file: &ScanFile) -> bool {
+        let digests = scan_file.digests();
+        let file_sha256 = digests.get(DigestAlgorithm::Sha256);
+        if digests.partial || file_sha256.is_empty() {
+            warn!(
+                "[Suppression {}] No full sha256 of {}, skip the sha256 check",
+                self.suppression.id,
+                scan_file.path.display()
+            );
+            return false;
+        }
+        sha256.trim().eq_ignore_ascii_case(file_sha256)
+    }
+
+    fn report(&self) -> DLPSuppression {
+        let suppression = &self.suppression;
+        DLPSuppression {
+            id: suppression.id.to_owned(),
+            owner: suppression.owner.to_owned(),
+            reason: suppression.reason.to_owned(),
+            expires: suppression.expires.to_owned(),
+        }
+    }
+}
+
+/// Move the hits covered by one of `suppressions` out of `hits`, the first
+/// suppression covering a hit is reported
+pub(crate) fn apply_suppressions<'a>(
+    suppressions: impl Iterator<Item = &'a CompiledSuppression> + Clone,
+    hits: impl IntoIterator<Item = DLPFileSecurity>,
+    scan_file: &ScanFile,
+) -> (Vec<DLPFileSecurity>, Vec<DLPSuppressedHit>) {
+    let mut kept = Vec::new();
+    let mut suppressed = Vec::new();
+    for hit in hits {
+        match suppressions
+            .clone()
+            .find(|suppression| suppression.covers(&hit, scan_file))
+        {
+            Some(suppression) => suppressed.push(DLPSuppressedHit {
+                hit,
+                suppression: suppression.report(),
+            }),
+            None => kept.push(hit),
+        }
+    }
+    (kept, suppressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+
+    use super::{apply_suppressions, CompiledSuppression};
+    use crate::{
+        matcher::{policy::PolicySnapshot, scan_file::ScanFile, GlobalFileScanFormat},
+        test_utils::{hit, policy, temp_file},
+        utils::common_utils::{digest_file_partial, DigestAlgorithm},
+    };
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn suppression(mut suppression: serde_json::Value) -> CompiledSuppression {
+        suppression["owner"] = "soc".into();
+        suppression["reason"] = "false positive".into();
+        CompiledSuppression::compile(serde_json::from_value(suppression).unwrap()).unwrap()
+    }
+
+    /// Ids of the hits 1 and 2 on a file holding `abc` which `suppressions`
+    /// cover at `now`
+    fn covered(name: &str, suppressions: &[CompiledSuppression], now: &str) -> Vec<i32> {
+        let path = temp_file(name, b"abc");
+        let config = PolicySnapshot::new(policy(Vec::new()), GlobalFileScanFormat::default());
+        let hash_cache = Mutex::default();
+        let now = now.parse::<DateTime<Utc>>().unwrap();
+        let scan_file = ScanFile::new(&path, &hash_cache, &config, now);
+        let (_, suppressed) =
+            apply_suppressions(suppressions.iter(), vec![hit(1, 1), hit(2, 1)], &scan_file);
+        std::fs::remove_file(&path).unwrap();
+        suppressed
+            .iter()
+            .map(|suppressed| suppressed.hit.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_expiry() {
+        let suppressions = [suppression(json!({
+            "rule_ids": [1],
+            "expires": "2026-03-01T00:00:00+08:00"
+        }))];
+        assert_eq!(
+            covered("suppression_expiry", &suppressions, "2026-02-28T15:59:59Z"),
+            [1]
+        );
+        // the offset counts, this is the expiry instant
+        assert!(covered("suppression_expiry", &suppressions, "2026-02-28T16:00:00Z").is_empty());
+    }
+
+    #[test]
+    fn test_rule_ids_and_path_glob() {
+        let now = "2026-01-01T00:00:00Z";
+        let both = [suppression(json!({
+            "rule_ids": [2],
+            "path_glob": "**/*_suppression_conjunction"
+        }))];
+        assert_eq!(covered("suppression_conjunction", &both, now), [2]);
+        // the rule matches but not the path
+        assert!(covered("suppression_other_path", &both, now).is_empty());
+
+        let path_only = [suppression(json!({"path_glob": "**/*_suppression_path"}))];
+        assert_eq!(covered("suppression_path", &path_only, now), [1, 2]);
+    }
+
+    #[test]
+    fn test_sha256() {
+        let now = "2026-01-01T00:00:00Z";
+        let sha256 = [suppression(json!({
+            "sha256": format!(" {} ", ABC_SHA256.to_uppercase()),
+            "rule_ids": [1]
+        }))];
+        assert_eq!(covered("suppression_sha256", &sha256, now), [1]);
+
+        let other = [suppression(json!({"sha256": "00".repeat(32)}))];
+        assert!(covered("suppression_other_sha256", &other, now).is_empty());
+    }
+
+    #[test]
+    fn test_sha256_needs_full_digest() {
+        let path = temp_file("suppression_partial", b"abcdef");
+        let partial = digest_file_partial(&path, &DigestAlgorithm::DEFAULT.into(), 1).unwrap();
+        let suppressions = [
+            suppression(json!({"sha256": ABC_SHA256})),
+            suppression(json!({"sha256": partial.get(DigestAlgorithm::Sha256)})),
+        ];
+        let by_rule = [suppression(json!({"rule_ids": [2]}))];
+        let hash_cache = Mutex::default();
+        for partial_hash in [true, false] {
+            let mut file_scan_rule = policy(Vec::new());
+            file_scan_rule.hash_settings.max_hash_size = 2;
+            file_scan_rule.hash_settings.partial_hash = partial_hash;
+            file_scan_rule.hash_settings.partial_chunk_size = 1;
+            let config = PolicySnapshot::new(file_scan_rule, GlobalFileScanFormat::default());
+            let scan_file = ScanFile::new(&path, &hash_cache, &config, Utc::now());
+            assert_eq!(scan_file.digests().partial, partial_hash);
+
+            let (kept, _) = apply_suppressions(suppressions.iter(), vec![hit(1, 1)], &scan_file);
+            assert_eq!(kept.len(), 1, "partial_hash: {partial_hash}");
+            // other criteria still apply
+            let (_, suppressed) = apply_suppressions(by_rule.iter(), vec![hit(2, 1)], &scan_file);
+            assert_eq!(suppressed.len(), 1);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,3 +1,7 @@
 pub mod agent_model;
+pub mod explain_model;
 pub mod fs_model;
+pub mod functions;
 pub mod raw_model;
+pub mod schedule;
+pub mod scoring;
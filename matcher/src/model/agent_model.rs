@@ -1,11 +1,93 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use super::fs_model::RuleAction;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DLPFileSecurity {
     pub id: i32,
     pub code: String,
-    #[serde(skip)]
+    #[serde(default)]
+    pub level: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<RuleAction>,
+    /// First container member which produced the hit, none when only the
+    /// file itself did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<DLPHitMember>,
+    /// Every container member which produced the hit
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<DLPHitMember>,
+    /// Data items behind the expression variables, up to the policy's
+    /// `evidence_limit`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evidence: Vec<DLPEvidence>,
+    /// For a composite policy, the hits satisfying its condition
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<i32>,
+}
+
+/// One data item reported by the content engine
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DLPEvidence {
+    pub id: i32,
+    pub location: String,
+    pub length: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// `subFileData` positions of the node holding the item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub node: Vec<usize>,
+}
+
+/// Overall severity of a file and the hit standing for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DLPFileSeverity {
     pub level: i32,
+    pub id: i32,
+    pub code: String,
+}
+
+/// Final decision across every hit, a hit without actions asks for audit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DLPVerdict {
+    pub action: RuleAction,
+    /// Hit which asked for `action` first, in hit order
+    pub id: i32,
+    pub code: String,
+    /// Every action asked for, strongest first
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DLPSuppressedHit {
+    #[serde(flatten)]
+    pub hit: DLPFileSecurity,
+    pub suppression: DLPSuppression,
+}
+
+/// The suppression which covered a hit
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DLPSuppression {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub owner: String,
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub expires: String,
+}
+
+/// A member of the scanned container, at any depth
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DLPHitMember {
+    pub desc: String,
+    pub format: String,
+    /// `desc` of each enclosing member then of this one, outermost first,
+    /// e.g. `["2023.zip", "q4/payroll.xlsx"]`
+    pub path: Vec<String>,
+    /// `subFileData` positions leading to the member
+    pub node: Vec<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -22,6 +104,19 @@ pub struct DLPFileInfo {
     pub file_sha256: String,
     #[serde(rename = "md5_hash")]
     pub file_md5: String,
+    /// Digests cover head + tail + size only, the file was over `max_hash_size`
+    #[serde(rename = "hash_partial", default)]
+    pub file_hash_partial: bool,
+    /// Digests of the algorithms chosen by the policy, by algorithm name
+    #[serde(rename = "hashes", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_hashes: BTreeMap<String, String>,
+    /// Fuzzy hash, present when the policy asks for it or a rule uses `similar_to`
+    #[serde(
+        rename = "ssdeep_hash",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub file_fuzzy_hash: String,
     pub create_time: u64,
     pub update_time: u64,
     #[serde(rename = "visit_time")]
@@ -38,4 +133,17 @@ pub struct DLPSensitiveFile {
     pub engine_result: String,
     pub file_url: String,
     pub found_time: u64,
+    /// Highest level among `file_securities`, which come sorted by level
+    /// then id. None when every hit is suppressed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<DLPFileSeverity>,
+    /// None when every hit is suppressed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict: Option<DLPVerdict>,
+    /// Hits covered by a suppression, left out of `severity` and `verdict`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suppressed: Vec<DLPSuppressedHit>,
+    /// `config_version` of the policy which produced the hits
+    #[serde(default)]
+    pub config_version: String,
 }
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::fs_model::RuleScope;
+
+/// Why each rule did or did not hit a file
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DLPMatchExplanation {
+    pub path: String,
+    pub config_version: String,
+    /// Set when no rule could be checked, e.g. the scan result is malformed
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    pub rules: Vec<DLPRuleExplanation>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DLPRuleExplanation {
+    pub id: i32,
+    pub code: String,
+    pub kind: HitKind,
+    /// The rule, registered document set or composite condition matched
+    pub hit: bool,
+    /// What became of the hit
+    pub disposition: HitDisposition,
+    /// Id of the suppression covering the hit
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub suppressed_by: String,
+    /// Rules making up a composite hit
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<i32>,
+    /// One entry per node the rule was checked on, none for registry and
+    /// composite entries
+    pub checks: Vec<DLPRuleCheck>,
+}
+
+/// Where a hit comes from
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HitKind {
+    Rule,
+    /// `hit` of a registered document set
+    Registry,
+    Composite,
+}
+
+/// Final disposition of a hit in the match result
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HitDisposition {
+    /// Nothing hit
+    Missed,
+    /// In `file_securities`
+    Reported,
+    /// In `suppressed`
+    Suppressed,
+    /// Only feeds composite policies, never reported
+    ComponentOnly,
+}
+
+/// Outcome of a rule on one node
+#[derive(Serialize, Debug, Clone)]
+pub struct DLPRuleCheck {
+    /// `subFileData` positions of the node, empty for the file itself
+    pub node: Vec<usize>,
+    pub desc: String,
+    pub scope: RuleScope,
+    /// Step which rejected the rule, or the last step passed
+    pub step: RuleStep,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub reason: String,
+    /// Values of the expression variables, null when unset
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, serde_json::Value>,
+}
+
+/// Rule checks, in evaluation order
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleStep {
+    /// `enabled`, `valid_from`/`valid_until` and `schedule`
+    Schedule,
+    FileTypes,
+    Path,
+    Encrypted,
+    Hidden,
+    FileSize,
+    Data,
+    Expr,
+}
@@ -1,8 +1,14 @@
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::File,
+    io::BufReader,
+};
 
 use evalexpr::HashMapContext;
 use serde::{Deserialize, Serialize};
 
+use crate::{fs_error::Error, utils::common_utils::DigestAlgorithm};
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FileScanRule {
     pub id: i32,
@@ -16,11 +22,421 @@ pub struct FileScanRule {
     pub expr: String,
     pub expr_context: HashMapContext,
     pub md5_check: bool,
+    #[serde(default)]
+    pub scope: RuleScope,
+    #[serde(default)]
+    pub path_filter: PathFilter,
+    /// What the agent should do on a hit, resolved across hits by
+    /// [`VerdictSettings`]. None means audit only.
+    #[serde(default)]
+    pub actions: Vec<RuleAction>,
+    /// A disabled rule stays in the policy but never hits
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// RFC 3339 time the rule starts hitting, e.g. `2024-01-01T00:00:00+08:00`,
+    /// no start when empty
+    #[serde(default)]
+    pub valid_from: String,
+    /// RFC 3339 time the rule stops hitting, no end when empty
+    #[serde(default)]
+    pub valid_until: String,
+    /// Recurring hours the rule hits in, always when none
+    #[serde(default)]
+    pub schedule: Option<RuleSchedule>,
+    /// The rule only feeds [`CompositePolicy`] conditions, its own hits are
+    /// not reported
+    #[serde(default)]
+    pub component_only: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A policy hitting when its condition over rule hits holds, reported like
+/// a rule hit
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompositePolicy {
+    /// Shares the id space of rules and registry hits
+    pub id: i32,
+    pub code: String,
+    pub level: i32,
+    #[serde(default)]
+    pub actions: Vec<RuleAction>,
+    pub condition: PolicyCondition,
+}
+
+/// Boolean logic over the ids of rules and registry hits, e.g.
+/// `{"and": [{"rule": 1}, {"not": {"rule": 2}}]}`. It has to need some hit,
+/// a `not` alone is rejected.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyCondition {
+    /// The rule hit the file
+    Rule(i32),
+    And(Vec<PolicyCondition>),
+    Or(Vec<PolicyCondition>),
+    Not(Box<PolicyCondition>),
+    /// At least `k` of the conditions hold
+    KOfN {
+        k: usize,
+        of: Vec<PolicyCondition>,
+    },
+}
+
+impl PolicyCondition {
+    pub fn is_met(&self, hit: &dyn Fn(i32) -> bool) -> bool {
+        match self {
+            PolicyCondition::Rule(id) => hit(*id),
+            PolicyCondition::And(conditions) => conditions.iter().all(|c| c.is_met(hit)),
+            PolicyCondition::Or(conditions) => conditions.iter().any(|c| c.is_met(hit)),
+            PolicyCondition::Not(condition) => !condition.is_met(hit),
+            PolicyCondition::KOfN { k, of } => of.iter().filter(|c| c.is_met(hit)).count() >= *k,
+        }
+    }
+
+    /// Every rule id referenced, with whether it sits under a `not`
+    pub fn rule_ids(&self) -> Vec<(i32, bool)> {
+        let mut rule_ids = Vec::new();
+        self.collect_rule_ids(false, &mut rule_ids);
+        rule_ids
+    }
+
+    fn collect_rule_ids(&self, negated: bool, rule_ids: &mut Vec<(i32, bool)>) {
+        match self {
+            PolicyCondition::Rule(id) => rule_ids.push((*id, negated)),
+            PolicyCondition::And(conditions)
+            | PolicyCondition::Or(conditions)
+            | PolicyCondition::KOfN { of: conditions, .. } => {
+                for condition in conditions {
+                    condition.collect_rule_ids(negated, rule_ids);
+                }
+            }
+            PolicyCondition::Not(condition) => condition.collect_rule_ids(!negated, rule_ids),
+        }
+    }
+}
+
+/// Weekly hours a rule is active in
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RuleSchedule {
+    /// `mon` to `sun` (or full names), every day when empty
+    pub weekdays: Vec<String>,
+    /// Hours of the day, all day when empty
+    pub hours: Vec<HourRange>,
+    /// Timezone weekdays and hours are read in, a UTC offset like `+08:00`
+    /// or an IANA name like `Asia/Shanghai`, UTC when empty
+    pub timezone: String,
+}
+
+/// Hours `[start, end)` in 0..=24, a range with `start > end` wraps past
+/// midnight
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct HourRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Enforcement asked for by a rule, the value is its verdict code over FFI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum RuleAction {
+    Audit = 1,
+    Alert = 2,
+    Notify = 3,
+    Encrypt = 4,
+    Quarantine = 5,
+    Block = 6,
+}
+
+impl RuleAction {
+    /// Strongest first
+    pub const DEFAULT_PRECEDENCE: [RuleAction; 6] = [
+        RuleAction::Block,
+        RuleAction::Quarantine,
+        RuleAction::Encrypt,
+        RuleAction::Alert,
+        RuleAction::Notify,
+        RuleAction::Audit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleAction::Audit => "audit",
+            RuleAction::Alert => "alert",
+            RuleAction::Notify => "notify",
+            RuleAction::Encrypt => "encrypt",
+            RuleAction::Quarantine => "quarantine",
+            RuleAction::Block => "block",
+        }
+    }
+
+    /// Verdict code reported over FFI
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+}
+
+/// Known false positive, the hits it covers are reported as suppressed.
+///
+/// Every criterion set must match, at least one has to be set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Suppression {
+    /// Reference for analysts, e.g. a ticket number
+    #[serde(default)]
+    pub id: String,
+    /// File SHA-256, any file when empty. Only compared with a full file
+    /// digest, files hashed partially or not at all never match.
+    #[serde(default)]
+    pub sha256: String,
+    /// Glob on the full path, `*` stays within one path component and `**`
+    /// crosses them, any path when empty
+    #[serde(default)]
+    pub path_glob: String,
+    /// Rules whose hits are covered, any rule when empty
+    #[serde(default)]
+    pub rule_ids: Vec<i32>,
+    pub owner: String,
+    pub reason: String,
+    /// RFC 3339 time the suppression stops applying, never when empty
+    #[serde(default)]
+    pub expires: String,
+}
+
+/// How the final verdict is resolved across hits
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VerdictSettings {
+    /// Actions strongest first, the verdict is the first one any hit asks
+    /// for. Actions left out rank after the listed ones, in
+    /// [`RuleAction::DEFAULT_PRECEDENCE`] order.
+    pub precedence: Vec<RuleAction>,
+}
+
+impl VerdictSettings {
+    /// Every action, strongest first
+    pub fn full_precedence(&self) -> Vec<RuleAction> {
+        let mut precedence = Vec::with_capacity(RuleAction::DEFAULT_PRECEDENCE.len());
+        for action in self
+            .precedence
+            .iter()
+            .chain(RuleAction::DEFAULT_PRECEDENCE.iter())
+        {
+            if !precedence.contains(action) {
+                precedence.push(*action);
+            }
+        }
+        precedence
+    }
+}
+
+/// Where on disk a rule applies, checked before its expression
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PathFilter {
+    /// When any pattern is set, the file has to match one of them
+    pub include: PathPatterns,
+    /// A file matching any pattern is left out
+    pub exclude: PathPatterns,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PathPatterns {
+    /// Globs over the full path, `*` stays within one path component
+    pub path_globs: Vec<String>,
+    pub path_regex: Vec<String>,
+    /// Globs over the file name
+    pub name_globs: Vec<String>,
+    pub name_regex: Vec<String>,
+    /// Extensions without the dot, case insensitive
+    pub extensions: Vec<String>,
 }
 
+impl PathPatterns {
+    pub fn is_empty(&self) -> bool {
+        self.path_globs.is_empty()
+            && self.path_regex.is_empty()
+            && self.name_globs.is_empty()
+            && self.name_regex.is_empty()
+            && self.extensions.is_empty()
+    }
+}
+
+/// What a rule expression is evaluated against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleScope {
+    /// The file and each container member on its own
+    #[default]
+    Node,
+    /// Once per file, over the data of the file and all its members merged:
+    /// counts, lengths and dictionary scores add up across members
+    Container,
+}
+
+/// How the overall file severity is picked from the hits
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SeveritySettings {
+    pub tie_break: SeverityTieBreak,
+}
+
+/// Which hit stands for the file among those at the highest level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeverityTieBreak {
+    #[default]
+    LowestId,
+    HighestId,
+    /// First in `file_scan_rules`, registry hits after every rule
+    PolicyOrder,
+}
+
+/// Score contributions of one data id
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FileDigitalDictionary {
-    pub target_id: i32,
+    /// Single target form: each item adds `value` to `target_id`
+    pub target_id: Option<i32>,
+    #[serde(default)]
     pub target_threshold: i32,
+    #[serde(default)]
     pub value: i32,
+    /// Further targets fed by the same id
+    #[serde(default)]
+    pub targets: Vec<DictionaryTarget>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DictionaryTarget {
+    pub target_id: i32,
+    /// Added per item, negative for exclusion terms
+    pub weight: i32,
+    /// Score at which `{location}{target_id}` is set
+    pub threshold: i32,
+}
+
+impl FileDigitalDictionary {
+    /// Every target this id contributes to, the single target form first
+    pub fn contributions(&self) -> impl Iterator<Item = DictionaryTarget> + '_ {
+        self.target_id
+            .map(|target_id| DictionaryTarget {
+                target_id,
+                weight: self.value,
+                threshold: self.target_threshold,
+            })
+            .into_iter()
+            .chain(self.targets.iter().cloned())
+    }
+}
+
+/// How file digests are computed for a policy
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HashSettings {
+    /// Files above this size are not hashed in full, 0 means no limit
+    pub max_hash_size: u64,
+    /// Above `max_hash_size`, hash head + tail + size instead of skipping
+    pub partial_hash: bool,
+    /// Bytes taken from each end of the file by a partial hash
+    pub partial_chunk_size: u64,
+    /// Digests reported in `hashes`, on top of sha256 and md5 which are
+    /// always computed for `sha256_hash` and `md5_hash`
+    pub algorithms: BTreeSet<DigestAlgorithm>,
+}
+
+impl Default for HashSettings {
+    fn default() -> Self {
+        Self {
+            max_hash_size: 0,
+            partial_hash: false,
+            partial_chunk_size: 1024 * 1024,
+            algorithms: BTreeSet::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashMode {
+    Full,
+    /// Head and tail of `partial_chunk_size` bytes, plus the file size
+    Partial(u64),
+    Skip,
+}
+
+impl HashSettings {
+    /// Algorithms the policy picked plus [`DigestAlgorithm::DEFAULT`]
+    pub fn all_algorithms(&self) -> BTreeSet<DigestAlgorithm> {
+        let mut algorithms = self.algorithms.clone();
+        algorithms.extend(DigestAlgorithm::DEFAULT);
+        algorithms
+    }
+
+    pub fn mode_for(&self, file_size: u64) -> HashMode {
+        if self.max_hash_size == 0 || file_size <= self.max_hash_size {
+            HashMode::Full
+        } else if self.partial_hash {
+            HashMode::Partial(self.partial_chunk_size)
+        } else {
+            HashMode::Skip
+        }
+    }
+}
+
+/// Digests of registered confidential documents, grouped by label
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DocumentRegistry {
+    /// JSON files each holding a list of `sets`, for registries too large
+    /// to inline. The policy loader merges them into `sets`.
+    pub files: Vec<String>,
+    pub sets: Vec<RegisteredDocumentSet>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RegisteredDocumentSet {
+    /// Name used by `in_registry(label)` in rule expressions
+    pub label: String,
+    /// Hex digests of the registered documents, files hashed partially or
+    /// not at all are never looked up
+    pub sha256: Vec<String>,
+    pub md5: Vec<String>,
+    /// Raise a hit on its own when a file belongs to the set
+    pub hit: Option<RegistryHit>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RegistryHit {
+    pub id: i32,
+    pub code: String,
+    pub level: i32,
+    #[serde(default)]
+    pub actions: Vec<RuleAction>,
+}
+
+impl DocumentRegistry {
+    /// Move the sets of every file in `files` into `sets`, failed files are
+    /// reported with their position and name and left in `files`
+    pub fn load_files(&mut self) -> Vec<(usize, String, Error)> {
+        let mut errors = Vec::new();
+        let mut remaining = Vec::new();
+        for (position, file) in std::mem::take(&mut self.files).into_iter().enumerate() {
+            let sets = File::open(&file).map_err(Error::from).and_then(|file| {
+                serde_json::from_reader::<_, Vec<RegisteredDocumentSet>>(BufReader::new(file))
+                    .map_err(Error::from)
+            });
+            match sets {
+                Ok(sets) => self.sets.extend(sets),
+                Err(e) => {
+                    errors.push((position, file.to_owned(), e));
+                    remaining.push(file);
+                }
+            }
+        }
+        self.files = remaining;
+        errors
+    }
 }
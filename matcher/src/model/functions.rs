@@ -0,0 +1,406 @@
+//! Functions available to rule expressions, on top of `cvtBoolToInt`.
+//!
+//! Node functions, over the data of the node under evaluation (or of the
+//! whole container for container rules):
+//!
+//! - `count(id)`: number of items with `id`, in any location
+//! - `count_in(location, id)`: number of items with `id` in `location`
+//! - `any_of(id, ...)`: whether some listed id has an item
+//! - `all_of(id, ...)`: whether every listed id has an item
+//! - `score(target_id)`: dictionary score of the target summed over all
+//!   locations, 0 when nothing contributed
+//!
+//! File functions, over the scanned file:
+//!
+//! - `file_size()`: size in bytes, the one `min_file_size`/`max_file_size` check
+//! - `file_ext()`: lowercase extension without the dot, empty when none
+//! - `path_matches(glob)`: whether the full path matches `glob`, `*` stays
+//!   within one path component and `**` crosses them
+//! - `name_matches(glob)`: whether the file name matches `glob`
+//! - `file_age_days()`: whole days since the last modification, -1 when unknown
+//!
+//! and the path variables `file_path`, `file_name` and `file_dir`, which the
+//! builtin `str::regex_matches(file_path, regex)` can test.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use evalexpr::{
+    ContextWithMutableFunctions, ContextWithMutableVariables, EvalexprError, EvalexprResult,
+    Function, HashMapContext, Value,
+};
+use globset::{Glob, GlobBuilder, GlobMatcher};
+
+use super::raw_model::RawScanResultData;
+
+pub const NODE_FUNCTIONS: [&str; 5] = ["count", "count_in", "any_of", "all_of", "score"];
+pub const FILE_FUNCTIONS: [&str; 5] = [
+    "file_size",
+    "file_ext",
+    "path_matches",
+    "name_matches",
+    "file_age_days",
+];
+pub const PATH_VARIABLES: [&str; 3] = ["file_path", "file_name", "file_dir"];
+
+/// Globs passed as constants to `path_matches` and `name_matches`, compiled
+/// once per policy
+pub type FileGlobs = HashMap<String, GlobMatcher>;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Item counts and scores of a node, shared by its node functions
+#[derive(Default)]
+struct NodeData {
+    by_id: HashMap<i64, i64>,
+    by_location: HashMap<(String, i64), i64>,
+    scores: HashMap<i64, i64>,
+}
+
+/// Register the node functions over `data` and its dictionary `scores`
+pub fn set_node_functions(
+    context: &mut HashMapContext,
+    data: &[RawScanResultData],
+    scores: &BTreeMap<(&str, i32), i64>,
+) {
+    let mut node_data = NodeData::default();
+    for item in data {
+        let id = item.id as i64;
+        *node_data.by_id.entry(id).or_default() += 1;
+        *node_data
+            .by_location
+            .entry((item.location.to_owned(), id))
+            .or_default() += 1;
+    }
+    for (&(_, target_id), score) in scores {
+        *node_data.scores.entry(target_id as i64).or_default() += score;
+    }
+    let node_data = Arc::new(node_data);
+
+    let data = node_data.clone();
+    let _ = context.set_function(
+        "count".to_owned(),
+        Function::new(move |argument| {
+            let id = argument.as_int()?;
+            Ok(Value::Int(data.count(id)))
+        }),
+    );
+
+    let data = node_data.clone();
+    let _ = context.set_function(
+        "count_in".to_owned(),
+        Function::new(move |argument| {
+            let arguments = argument.as_fixed_len_tuple(2)?;
+            let location = arguments[0].as_string()?;
+            let id = arguments[1].as_int()?;
+            let count = data.by_location.get(&(location, id)).copied();
+            Ok(Value::Int(count.unwrap_or_default()))
+        }),
+    );
+
+    let data = node_data.clone();
+    let _ = context.set_function(
+        "any_of".to_owned(),
+        Function::new(move |argument| {
+            let ids = int_arguments(argument)?;
+            Ok(Value::Boolean(ids.into_iter().any(|id| data.count(id) > 0)))
+        }),
+    );
+
+    let data = node_data.clone();
+    let _ = context.set_function(
+        "all_of".to_owned(),
+        Function::new(move |argument| {
+            let ids = int_arguments(argument)?;
+            Ok(Value::Boolean(ids.into_iter().all(|id| data.count(id) > 0)))
+        }),
+    );
+
+    let data = node_data;
+    let _ = context.set_function(
+        "score".to_owned(),
+        Function::new(move |argument| {
+            let target_id = argument.as_int()?;
+            let score = data.scores.get(&target_id).copied();
+            Ok(Value::Int(score.unwrap_or_default()))
+        }),
+    );
+}
+
+impl NodeData {
+    fn count(&self, id: i64) -> i64 {
+        self.by_id.get(&id).copied().unwrap_or_default()
+    }
+}
+
+/// One id, or a tuple of them
+fn int_arguments(argument: &Value) -> EvalexprResult<Vec<i64>> {
+    match argument {
+        Value::Tuple(arguments) => arguments.iter().map(Value::as_int).collect(),
+        argument => Ok(vec![argument.as_int()?]),
+    }
+}
+
+/// What the file functions know of the scanned file
+#[derive(Debug, Clone)]
+pub struct FileFacts {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub now: SystemTime,
+}
+
+impl FileFacts {
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Lowercase extension without the dot
+    pub fn ext(&self) -> String {
+        self.path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    }
+
+    pub fn dir(&self) -> String {
+        self.path
+            .parent()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Set the path variables of `facts`
+pub fn set_path_variables(context: &mut HashMapContext, facts: &FileFacts) {
+    let values = [
+        facts.path.to_string_lossy().to_string(),
+        facts.name(),
+        facts.dir(),
+    ];
+    for (variable, value) in PATH_VARIABLES.into_iter().zip(values) {
+        let _ = context.set_value(variable.to_owned(), value.into());
+    }
+}
+
+/// Register the file functions over `facts`, globs missing from `globs` are
+/// compiled on each call
+pub fn set_file_functions(context: &mut HashMapContext, facts: &FileFacts, globs: &Arc<FileGlobs>) {
+    let size = facts.size as i64;
+    let _ = context.set_function(
+        "file_size".to_owned(),
+        Function::new(move |argument| {
+            argument.as_empty()?;
+            Ok(Value::Int(size))
+        }),
+    );
+
+    let ext = facts.ext();
+    let _ = context.set_function(
+        "file_ext".to_owned(),
+        Function::new(move |argument| {
+            argument.as_empty()?;
+            Ok(Value::String(ext.to_owned()))
+        }),
+    );
+
+    let path = Arc::new(facts.path.to_owned());
+    let path_globs = globs.clone();
+    let _ = context.set_function(
+        "path_matches".to_owned(),
+        Function::new(move |argument| {
+            let glob = argument.as_string()?;
+            Ok(Value::Boolean(glob_matches(&path, &glob, &path_globs)?))
+        }),
+    );
+
+    let name = Arc::new(facts.name());
+    let name_globs = globs.clone();
+    let _ = context.set_function(
+        "name_matches".to_owned(),
+        Function::new(move |argument| {
+            let glob = argument.as_string()?;
+            Ok(Value::Boolean(glob_matches(
+                Path::new(name.as_str()),
+                &glob,
+                &name_globs,
+            )?))
+        }),
+    );
+
+    let age_days = facts
+        .modified
+        .map_or(-1, |modified| match facts.now.duration_since(modified) {
+            Ok(age) => (age.as_secs() / SECONDS_PER_DAY) as i64,
+            // modified in the future, clock skew
+            Err(_) => 0,
+        });
+    let _ = context.set_function(
+        "file_age_days".to_owned(),
+        Function::new(move |argument| {
+            argument.as_empty()?;
+            Ok(Value::Int(age_days))
+        }),
+    );
+}
+
+/// Glob where `*` stays within one path component, as everywhere in rules
+pub fn build_glob(glob: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(glob).literal_separator(true).build()
+}
+
+fn glob_matches(path: &Path, glob: &str, globs: &FileGlobs) -> EvalexprResult<bool> {
+    if let Some(matcher) = globs.get(glob) {
+        return Ok(matcher.is_match(path));
+    }
+    let matcher = build_glob(glob)
+        .map_err(|e| EvalexprError::CustomMessage(format!("bad glob {glob}: {e}")))?
+        .compile_matcher();
+    Ok(matcher.is_match(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        path::PathBuf,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use evalexpr::{eval_with_context, HashMapContext, Value};
+
+    use super::{set_file_functions, set_node_functions, FileFacts};
+    use crate::model::raw_model::RawScanResultData;
+
+    fn item(id: i32, location: &str) -> RawScanResultData {
+        RawScanResultData {
+            id,
+            length: 1,
+            location: location.to_owned(),
+            offset: None,
+        }
+    }
+
+    fn node_context() -> HashMapContext {
+        let data = vec![
+            item(101, "body"),
+            item(101, "body"),
+            item(101, "header"),
+            item(7, "body"),
+        ];
+        let scores = BTreeMap::from([(("body", 9), 3), (("header", 9), -1)]);
+        let mut context = HashMapContext::new();
+        set_node_functions(&mut context, &data, &scores);
+        context
+    }
+
+    fn file_context(path: &str) -> HashMapContext {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100 * 24 * 60 * 60);
+        let facts = FileFacts {
+            path: PathBuf::from(path),
+            size: 4096,
+            modified: Some(now - Duration::from_secs(3 * 24 * 60 * 60 + 5)),
+            now,
+        };
+        let mut context = HashMapContext::new();
+        set_file_functions(&mut context, &facts, &Arc::default());
+        context
+    }
+
+    #[test]
+    fn test_count_functions() {
+        let context = node_context();
+        assert_eq!(eval_with_context("count(101)", &context), Ok(Value::Int(3)));
+        assert_eq!(eval_with_context("count(5)", &context), Ok(Value::Int(0)));
+        assert_eq!(
+            eval_with_context("count_in(\"body\", 101)", &context),
+            Ok(Value::Int(2))
+        );
+        assert_eq!(
+            eval_with_context("count_in(\"footer\", 101)", &context),
+            Ok(Value::Int(0))
+        );
+    }
+
+    #[test]
+    fn test_any_all_of() {
+        let context = node_context();
+        assert_eq!(
+            eval_with_context("any_of(5, 7)", &context),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            eval_with_context("any_of(5)", &context),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(
+            eval_with_context("all_of(101, 7)", &context),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            eval_with_context("all_of(101, 5)", &context),
+            Ok(Value::Boolean(false))
+        );
+        assert!(eval_with_context("any_of(\"101\")", &context).is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        let context = node_context();
+        assert_eq!(eval_with_context("score(9)", &context), Ok(Value::Int(2)));
+        assert_eq!(eval_with_context("score(10)", &context), Ok(Value::Int(0)));
+    }
+
+    #[test]
+    fn test_file_functions() {
+        let context = file_context("/data/q4/Payroll.XLSX");
+        assert_eq!(
+            eval_with_context("file_size()", &context),
+            Ok(Value::Int(4096))
+        );
+        assert_eq!(
+            eval_with_context("file_ext()", &context),
+            Ok(Value::String("xlsx".to_owned()))
+        );
+        assert_eq!(
+            eval_with_context("file_age_days()", &context),
+            Ok(Value::Int(3))
+        );
+
+        let context = file_context("/data/noext");
+        assert_eq!(
+            eval_with_context("file_ext()", &context),
+            Ok(Value::String(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_path_matches() {
+        let context = file_context("/data/q4/Payroll.XLSX");
+        assert_eq!(
+            eval_with_context("path_matches(\"/data/**/*.XLSX\")", &context),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            eval_with_context("path_matches(\"/data/*.XLSX\")", &context),
+            Ok(Value::Boolean(false))
+        );
+        assert!(eval_with_context("path_matches(\"[\")", &context).is_err());
+        assert_eq!(
+            eval_with_context("name_matches(\"payroll.*\")", &context),
+            Ok(Value::Boolean(false))
+        );
+        assert_eq!(
+            eval_with_context("name_matches(\"Payroll.*\")", &context),
+            Ok(Value::Boolean(true))
+        );
+    }
+}
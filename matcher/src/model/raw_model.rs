@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::BTreeMap;
 
 use evalexpr::{
     ContextWithMutableFunctions, ContextWithMutableVariables, EvalexprError, Function,
@@ -6,20 +6,29 @@ use evalexpr::{
 };
 use serde::{Deserialize, Serialize};
 
-use super::fs_model::FileDigitalDictionary;
+use super::{functions::set_node_functions, scoring::ScoringDictionary};
 
-pub trait TRawScanResult {
-    fn update_context(
-        &self,
-        context: HashMapContext,
-        dictionary: &HashMap<i32, FileDigitalDictionary>,
-    ) -> HashMapContext;
-    fn get_dlp_type(&self) -> i32;
-    fn get_format(&self) -> String;
-    fn need_check_encrypted(&self) -> bool;
-    fn need_check_hidden(&self) -> bool;
+/// Register the functions available to every rule expression
+pub fn with_builtin_functions(mut context: HashMapContext) -> HashMapContext {
+    let _ = context.set_function(
+        "cvtBoolToInt".to_owned(),
+        Function::new(|argument| {
+            if let Ok(boolean) = argument.as_boolean() {
+                if boolean {
+                    Ok(Value::Int(1))
+                } else {
+                    Ok(Value::Int(0))
+                }
+            } else {
+                Err(EvalexprError::expected_boolean(argument.clone()))
+            }
+        }),
+    );
+    context
 }
 
+/// One scanned node, the file itself or a member of a container, which may
+/// hold members of its own (zip in eml in zip)
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RawScanResult {
     #[serde(rename = "categoryId")]
@@ -32,22 +41,8 @@ pub struct RawScanResult {
     pub encrypted: i32,
     #[serde(default)]
     pub hidden: i32,
-    #[serde(rename = "subFileData")]
-    pub sub_data: Option<Vec<RawScanResultSubData>>,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct RawScanResultSubData {
-    #[serde(rename = "categoryId")]
-    pub category_id: i32,
-    #[serde(default)]
-    pub desc: String,
-    pub format: String,
-    pub data: Vec<RawScanResultData>,
-    #[serde(default)]
-    pub encrypted: i32,
-    #[serde(default)]
-    pub hidden: i32,
+    #[serde(rename = "subFileData", default)]
+    pub sub_data: Option<Vec<RawScanResult>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -56,132 +51,248 @@ pub struct RawScanResultData {
     #[serde(default)]
     pub length: i32,
     pub location: String,
+    /// Byte offset of the item in the scanned content, when the engine reports it
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// Aggregate of every data item sharing one location and id
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataStats {
+    pub count: i64,
+    pub total_len: i64,
+    pub max_len: i64,
+    /// Smallest reported offset, -1 when no item has one
+    pub first_offset: i64,
+    /// Length of the last item, backs the legacy `{location}{id}` variable
+    pub last_len: i64,
+}
+
+impl DataStats {
+    /// Variables set for `{location}{id}`
+    pub const SUFFIXES: [&'static str; 4] = ["_count", "_total_len", "_max_len", "_first_offset"];
+
+    fn add(&mut self, item: &RawScanResultData) {
+        self.merge(&DataStats {
+            count: 1,
+            total_len: item.length as i64,
+            max_len: item.length as i64,
+            first_offset: item.offset.unwrap_or(-1),
+            last_len: item.length as i64,
+        });
+    }
+
+    /// Fold `other`, seen after `self`, into `self`
+    pub fn merge(&mut self, other: &DataStats) {
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        self.count += other.count;
+        self.total_len += other.total_len;
+        self.max_len = self.max_len.max(other.max_len);
+        self.first_offset = match (self.first_offset, other.first_offset) {
+            (-1, offset) | (offset, -1) => offset,
+            (a, b) => a.min(b),
+        };
+        self.last_len = other.last_len;
+    }
+
+    /// Set `{key}` and its aggregate variables
+    pub fn update_context(&self, key: &str, context: &mut HashMapContext) {
+        let _ = context.set_value(key.to_owned(), self.last_len.into());
+        let values = [self.count, self.total_len, self.max_len, self.first_offset];
+        for (suffix, value) in Self::SUFFIXES.into_iter().zip(values) {
+            let _ = context.set_value(format!("{key}{suffix}"), value.into());
+        }
+    }
+}
+
+/// Aggregate data items by `{location}{id}`
+pub fn collect_data_stats(data: &[RawScanResultData]) -> BTreeMap<String, DataStats> {
+    let mut stats = BTreeMap::<String, DataStats>::new();
+    for item in data {
+        stats
+            .entry(format!("{}{}", item.location, item.id))
+            .or_default()
+            .add(item);
+    }
+    stats
+}
+
+/// Data variables of one scan node:
+///
+/// - `{location}{id}`: length of the last item, kept for existing rules
+/// - `{location}{id}_count`: number of items
+/// - `{location}{id}_total_len` / `_max_len`: sum and max of item lengths
+/// - `{location}{id}_first_offset`: smallest item offset, -1 when unknown
+/// - `score_{location}_{target_id}` / `{location}{target_id}`: dictionary
+///   scores, see [`ScoringDictionary::update_context`]
+///
+/// plus the node functions of [`super::functions`].
+fn update_data_context(
+    data: &[RawScanResultData],
+    mut context: HashMapContext,
+    dictionary: &ScoringDictionary,
+) -> HashMapContext {
+    for (key, stats) in collect_data_stats(data) {
+        stats.update_context(&key, &mut context);
+    }
+    let scores = dictionary.scores(data);
+    dictionary.update_context(&scores, &mut context);
+    set_node_functions(&mut context, data, &scores);
+    with_builtin_functions(context)
 }
 
-impl TRawScanResult for RawScanResult {
-    fn update_context(
+impl RawScanResult {
+    pub fn update_context(
         &self,
-        mut context: HashMapContext,
-        dictionary: &HashMap<i32, FileDigitalDictionary>,
+        context: HashMapContext,
+        dictionary: &ScoringDictionary,
     ) -> HashMapContext {
-        let mut temp_map = HashMap::<String, i32>::new();
-        let mut mapped_key_set = HashSet::<String>::new();
-
-        for item in &self.data {
-            if let Some(entry) = dictionary.get(&item.id) {
-                let mapped_key = format!("{}{}", item.location, entry.target_id);
-                if !mapped_key_set.contains(&mapped_key) {
-                    if let Some(current_value) = temp_map.get(&mapped_key) {
-                        let new_one = current_value + entry.value;
-                        if new_one >= entry.target_threshold {
-                            mapped_key_set.insert(mapped_key.clone());
-                            let _ = context.set_value(mapped_key, 1.into());
-                        } else {
-                            temp_map.insert(mapped_key, new_one);
-                        }
-                    } else {
-                        temp_map.insert(mapped_key, entry.value);
-                    }
-                }
-            }
+        update_data_context(&self.data, context, dictionary)
+    }
 
-            let key = format!("{}{}", item.location, item.id);
-            let _ = context.set_value(key, (item.length as i64).into());
-        }
-        let _ = context.set_function(
-            "cvtBoolToInt".to_owned(),
-            Function::new(|argument| {
-                if let Ok(boolean) = argument.as_boolean() {
-                    if boolean {
-                        Ok(Value::Int(1))
-                    } else {
-                        Ok(Value::Int(0))
-                    }
-                } else {
-                    Err(EvalexprError::expected_boolean(argument.clone()))
-                }
-            }),
-        );
-        context
+    /// Context of the whole tree, as if every node's data were in one node
+    pub fn update_container_context(
+        &self,
+        context: HashMapContext,
+        dictionary: &ScoringDictionary,
+    ) -> HashMapContext {
+        let data = self
+            .nodes()
+            .into_iter()
+            .flat_map(|(_, node)| node.data.iter().cloned())
+            .collect::<Vec<_>>();
+        update_data_context(&data, context, dictionary)
     }
 
-    fn get_dlp_type(&self) -> i32 {
+    pub fn get_dlp_type(&self) -> i32 {
         self.category_id
     }
 
-    fn get_format(&self) -> String {
+    pub fn get_format(&self) -> String {
         self.format.clone()
     }
 
-    fn need_check_encrypted(&self) -> bool {
+    pub fn need_check_encrypted(&self) -> bool {
         self.encrypted != 0
     }
 
-    fn need_check_hidden(&self) -> bool {
+    pub fn need_check_hidden(&self) -> bool {
         self.hidden != 0
     }
-}
 
-impl TRawScanResult for RawScanResultSubData {
-    fn update_context(
-        &self,
-        mut context: HashMapContext,
-        dictionary: &HashMap<i32, FileDigitalDictionary>,
-    ) -> HashMapContext {
-        let mut temp_map = HashMap::<String, i32>::new();
-        let mut mapped_key_set = HashSet::<String>::new();
-
-        for item in &self.data {
-            if let Some(entry) = dictionary.get(&item.id) {
-                let mapped_key = format!("{}{}", item.location, entry.target_id);
-                if !mapped_key_set.contains(&mapped_key) {
-                    if let Some(current_value) = temp_map.get(&mapped_key) {
-                        let new_one = current_value + entry.value;
-                        if new_one >= entry.target_threshold {
-                            mapped_key_set.insert(mapped_key.clone());
-                            let _ = context.set_value(mapped_key, 1.into());
-                        } else {
-                            temp_map.insert(mapped_key, new_one);
-                        }
-                    } else {
-                        temp_map.insert(mapped_key, entry.value);
-                    }
+    fn children(&self) -> &[RawScanResult] {
+        self.sub_data.as_deref().unwrap_or_default()
+    }
+
+    /// Whether any node of the tree holds data
+    pub fn has_data(&self) -> bool {
+        !self.data.is_empty() || self.children().iter().any(RawScanResult::has_data)
+    }
+
+    /// Nodes from the root's child down to the one at `path`
+    pub fn lineage(&self, path: &[usize]) -> Vec<&RawScanResult> {
+        let mut node = self;
+        let mut lineage = Vec::with_capacity(path.len());
+        for position in path {
+            match node.children().get(*position) {
+                Some(child) => {
+                    lineage.push(child);
+                    node = child;
                 }
+                None => break,
             }
+        }
+        lineage
+    }
 
-            let key = format!("{}{}", item.location, item.id);
-            let _ = context.set_value(key, (item.length as i64).into());
+    /// Every node of the tree in depth-first order, with its path of
+    /// `sub_data` positions from the root, the root's path is empty
+    pub fn nodes(&self) -> Vec<(Vec<usize>, &RawScanResult)> {
+        let mut nodes = Vec::new();
+        let mut pending = vec![(Vec::new(), self)];
+        while let Some((path, node)) = pending.pop() {
+            for (position, child) in node.children().iter().enumerate().rev() {
+                let mut child_path = path.clone();
+                child_path.push(position);
+                pending.push((child_path, child));
+            }
+            nodes.push((path, node));
         }
-        let _ = context.set_function(
-            "cvtBoolToInt".to_owned(),
-            Function::new(|argument| {
-                if let Ok(boolean) = argument.as_boolean() {
-                    if boolean {
-                        Ok(Value::Int(1))
-                    } else {
-                        Ok(Value::Int(0))
-                    }
-                } else {
-                    Err(EvalexprError::expected_boolean(argument.clone()))
-                }
-            }),
-        );
-        context
+        nodes
     }
+}
 
-    fn get_dlp_type(&self) -> i32 {
-        self.category_id
+#[cfg(test)]
+mod tests {
+    use evalexpr::{Context, HashMapContext, Value};
+
+    use super::{collect_data_stats, DataStats, RawScanResult};
+    use crate::model::scoring::ScoringDictionary;
+
+    fn raw_result() -> RawScanResult {
+        serde_json::from_str(
+            r#"{"categoryId":1,"format":"zip","data":[
+                {"id":101,"length":5,"location":"body","offset":90},
+                {"id":101,"length":7,"location":"body"},
+                {"id":101,"length":4,"location":"body","offset":40},
+                {"id":102,"length":3,"location":"title"}
+            ],"subFileData":[{"categoryId":2,"format":"txt","data":[
+                {"id":101,"length":9,"location":"body","offset":10}
+            ]}]}"#,
+        )
+        .unwrap()
     }
 
-    fn get_format(&self) -> String {
-        self.format.clone()
+    fn value(context: &HashMapContext, key: &str) -> Option<i64> {
+        match context.get_value(key) {
+            Some(Value::Int(value)) => Some(*value),
+            _ => None,
+        }
     }
 
-    fn need_check_encrypted(&self) -> bool {
-        self.encrypted != 0
+    #[test]
+    fn test_collect_data_stats() {
+        let stats = collect_data_stats(&raw_result().data);
+        assert_eq!(
+            stats["body101"],
+            DataStats {
+                count: 3,
+                total_len: 16,
+                max_len: 7,
+                first_offset: 40,
+                last_len: 4,
+            }
+        );
+        // no item reports an offset
+        assert_eq!(stats["title102"].first_offset, -1);
+        assert_eq!(stats.len(), 2);
     }
 
-    fn need_check_hidden(&self) -> bool {
-        self.hidden != 0
+    #[test]
+    fn test_node_aggregates() {
+        let context =
+            raw_result().update_context(HashMapContext::new(), &ScoringDictionary::default());
+        assert_eq!(value(&context, "body101"), Some(4));
+        assert_eq!(value(&context, "body101_count"), Some(3));
+        assert_eq!(value(&context, "body101_total_len"), Some(16));
+        assert_eq!(value(&context, "body101_max_len"), Some(7));
+        assert_eq!(value(&context, "body101_first_offset"), Some(40));
+        assert_eq!(value(&context, "title102_count"), Some(1));
+        assert_eq!(value(&context, "title102_first_offset"), Some(-1));
+        assert_eq!(value(&context, "body102_count"), None);
+    }
+
+    #[test]
+    fn test_container_aggregates() {
+        let context = raw_result()
+            .update_container_context(HashMapContext::new(), &ScoringDictionary::default());
+        assert_eq!(value(&context, "body101"), Some(9));
+        assert_eq!(value(&context, "body101_count"), Some(4));
+        assert_eq!(value(&context, "body101_total_len"), Some(25));
+        assert_eq!(value(&context, "body101_max_len"), Some(9));
+        assert_eq!(value(&context, "body101_first_offset"), Some(10));
     }
 }
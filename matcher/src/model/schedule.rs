@@ -0,0 +1,284 @@
+//! When a rule is active: `enabled`, `valid_from`/`valid_until` and the
+//! weekly `schedule` of a [`FileScanRule`].
+//!
+//! Weekdays and hours are read in the schedule's timezone, so a range
+//! wrapping past midnight counts the hours after midnight to the next day.
+//! A named timezone follows its daylight saving rules.
+
+use std::fmt;
+
+use chrono::{DateTime, Datelike, FixedOffset, Offset, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+
+use super::fs_model::{FileScanRule, HourRange};
+
+/// Why a rule is not active at some time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inactive {
+    Disabled,
+    NotYetValid(DateTime<FixedOffset>),
+    Expired(DateTime<FixedOffset>),
+    /// Local time in the schedule's timezone
+    OffSchedule(DateTime<FixedOffset>),
+}
+
+impl fmt::Display for Inactive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inactive::Disabled => write!(f, "rule disabled"),
+            Inactive::NotYetValid(from) => write!(f, "not valid before {}", from.to_rfc3339()),
+            Inactive::Expired(until) => write!(f, "expired at {}", until.to_rfc3339()),
+            Inactive::OffSchedule(local) => {
+                write!(f, "off schedule at {}", local.format("%a %H:%M %:z"))
+            }
+        }
+    }
+}
+
+/// Activity settings of a rule, parsed once per policy
+#[derive(Debug, Clone)]
+pub struct ActiveWindow {
+    enabled: bool,
+    valid_from: Option<DateTime<FixedOffset>>,
+    valid_until: Option<DateTime<FixedOffset>>,
+    schedule: Option<WeeklySchedule>,
+}
+
+#[derive(Debug, Clone)]
+struct WeeklySchedule {
+    /// Every day when empty
+    weekdays: Vec<Weekday>,
+    /// All day when empty
+    hours: Vec<HourRange>,
+    timezone: ScheduleTimezone,
+}
+
+/// Timezone of a weekly schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTimezone {
+    Fixed(FixedOffset),
+    /// IANA name like `Asia/Shanghai`
+    Named(Tz),
+}
+
+impl ScheduleTimezone {
+    /// `now` as local time
+    pub fn local(&self, now: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            ScheduleTimezone::Fixed(offset) => now.with_timezone(offset),
+            ScheduleTimezone::Named(tz) => now.with_timezone(tz).fixed_offset(),
+        }
+    }
+}
+
+impl ActiveWindow {
+    pub fn new(rule: &FileScanRule) -> Result<Self, String> {
+        let schedule = match &rule.schedule {
+            Some(schedule) => {
+                for range in &schedule.hours {
+                    check_hour_range(range)?;
+                }
+                Some(WeeklySchedule {
+                    weekdays: schedule
+                        .weekdays
+                        .iter()
+                        .map(|day| parse_weekday(day))
+                        .collect::<Result<_, _>>()?,
+                    hours: schedule.hours.to_owned(),
+                    timezone: parse_timezone(&schedule.timezone)?,
+                })
+            }
+            None => None,
+        };
+        Ok(Self {
+            enabled: rule.enabled,
+            valid_from: parse_time(&rule.valid_from)?,
+            valid_until: parse_time(&rule.valid_until)?,
+            schedule,
+        })
+    }
+
+    /// Active at `now`, or why not
+    pub fn check(&self, now: DateTime<Utc>) -> Result<(), Inactive> {
+        if !self.enabled {
+            return Err(Inactive::Disabled);
+        }
+        if let Some(from) = self.valid_from.filter(|from| now < *from) {
+            return Err(Inactive::NotYetValid(from));
+        }
+        if let Some(until) = self.valid_until.filter(|until| now >= *until) {
+            return Err(Inactive::Expired(until));
+        }
+        if let Some(schedule) = &self.schedule {
+            let local = schedule.timezone.local(now);
+            let day = schedule.weekdays.is_empty() || schedule.weekdays.contains(&local.weekday());
+            let hour = local.hour();
+            let hours = schedule.hours.is_empty()
+                || schedule.hours.iter().any(|range| {
+                    if range.start < range.end {
+                        (range.start..range.end).contains(&hour)
+                    } else {
+                        hour >= range.start || hour < range.end
+                    }
+                });
+            if !day || !hours {
+                return Err(Inactive::OffSchedule(local));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// RFC 3339 time, none when empty
+pub fn parse_time(time: &str) -> Result<Option<DateTime<FixedOffset>>, String> {
+    if time.trim().is_empty() {
+        return Ok(None);
+    }
+    DateTime::parse_from_rfc3339(time.trim())
+        .map(Some)
+        .map_err(|e| format!("bad time {time}: {e}"))
+}
+
+/// `+08:00`, `-0530`, `+08`, `UTC` or an IANA name like `Asia/Shanghai`,
+/// UTC when empty
+pub fn parse_timezone(timezone: &str) -> Result<ScheduleTimezone, String> {
+    let timezone = timezone.trim();
+    if timezone.is_empty() || timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+        return Ok(ScheduleTimezone::Fixed(Utc.fix()));
+    }
+    let error = || {
+        format!(
+            "bad timezone {timezone}, expect an offset like +08:00 or a name like Asia/Shanghai"
+        )
+    };
+    let (sign, offset) = match timezone.split_at_checked(1) {
+        Some(("+", offset)) => (1, offset),
+        Some(("-", offset)) => (-1, offset),
+        _ => {
+            return timezone
+                .parse::<Tz>()
+                .map(ScheduleTimezone::Named)
+                .map_err(|_| error())
+        }
+    };
+    let digits = offset.replace(':', "");
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(error());
+    }
+    let hours = digits[..2].parse::<i32>().map_err(|_| error())?;
+    let minutes = digits[2..].parse::<i32>().unwrap_or_default();
+    if hours > 23 || minutes > 59 {
+        return Err(error());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .map(ScheduleTimezone::Fixed)
+        .ok_or_else(error)
+}
+
+pub fn parse_weekday(day: &str) -> Result<Weekday, String> {
+    day.trim()
+        .parse::<Weekday>()
+        .map_err(|_| format!("bad weekday {day}, expect mon to sun"))
+}
+
+pub fn check_hour_range(range: &HourRange) -> Result<(), String> {
+    if range.start > 24 || range.end > 24 || range.start == range.end {
+        return Err(format!(
+            "bad hour range [{}, {}), expect distinct hours in 0..=24",
+            range.start, range.end
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::{parse_timezone, ActiveWindow, Inactive};
+    use crate::model::fs_model::FileScanRule;
+
+    fn window(fields: &str) -> ActiveWindow {
+        let rule = format!(
+            r#"{{"id":1,"code":"R1","level":1,"file_types":[],"min_file_size":1,"max_file_size":10,"check_file_encrypted":false,"check_file_suffix":false,"expr":"","expr_context":{{"variables":{{}},"without_builtin_functions":false}},"md5_check":false{fields}}}"#
+        );
+        let rule = serde_json::from_str::<FileScanRule>(&rule).unwrap();
+        ActiveWindow::new(&rule).unwrap()
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_validity() {
+        let window = window(
+            r#","valid_from":"2024-01-01T00:00:00+08:00","valid_until":"2024-02-01T00:00:00+08:00""#,
+        );
+        assert!(matches!(
+            window.check(at("2023-12-31T15:59:59Z")),
+            Err(Inactive::NotYetValid(_))
+        ));
+        assert_eq!(window.check(at("2023-12-31T16:00:00Z")), Ok(()));
+        assert!(matches!(
+            window.check(at("2024-01-31T16:00:00Z")),
+            Err(Inactive::Expired(_))
+        ));
+        let disabled = self::window(r#","enabled":false"#);
+        assert_eq!(
+            disabled.check(at("2024-01-10T00:00:00Z")),
+            Err(Inactive::Disabled)
+        );
+    }
+
+    #[test]
+    fn test_schedule() {
+        // weekday nights in UTC+8, 2024-01-05 is a Friday
+        let window = window(
+            r#","schedule":{"weekdays":["mon","tue","wed","thu","fri"],"hours":[{"start":22,"end":6}],"timezone":"+08:00"}"#,
+        );
+        assert_eq!(window.check(at("2024-01-05T14:30:00Z")), Ok(()));
+        assert_eq!(window.check(at("2024-01-04T21:00:00Z")), Ok(()));
+        assert!(matches!(
+            window.check(at("2024-01-05T04:00:00Z")),
+            Err(Inactive::OffSchedule(_))
+        ));
+        // Saturday 01:00 local
+        assert!(matches!(
+            window.check(at("2024-01-05T17:00:00Z")),
+            Err(Inactive::OffSchedule(_))
+        ));
+    }
+
+    #[test]
+    fn test_named_timezone() {
+        // 09:00 to 17:00 in New York, 2024-03-10 02:00 springs forward
+        let window =
+            window(r#","schedule":{"hours":[{"start":9,"end":17}],"timezone":"America/New_York"}"#);
+        assert_eq!(window.check(at("2024-03-08T14:30:00Z")), Ok(()));
+        assert!(matches!(
+            window.check(at("2024-03-08T13:30:00Z")),
+            Err(Inactive::OffSchedule(_))
+        ));
+        assert_eq!(window.check(at("2024-03-11T13:30:00Z")), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        let offset = |timezone: &str, time: &str| {
+            parse_timezone(timezone)
+                .unwrap()
+                .local(at(time))
+                .offset()
+                .local_minus_utc()
+        };
+        assert_eq!(offset("", "2024-01-01T00:00:00Z"), 0);
+        assert_eq!(offset("+08:00", "2024-01-01T00:00:00Z"), 28800);
+        assert_eq!(offset("-0530", "2024-01-01T00:00:00Z"), -19800);
+        assert_eq!(offset("Asia/Shanghai", "2024-01-01T00:00:00Z"), 28800);
+        assert_eq!(offset("Europe/Berlin", "2024-01-01T00:00:00Z"), 3600);
+        assert_eq!(offset("Europe/Berlin", "2024-07-01T00:00:00Z"), 7200);
+        assert!(parse_timezone("Asia/Atlantis").is_err());
+        assert!(parse_timezone("+25:00").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, HashMap};
+
+use evalexpr::{ContextWithMutableVariables, HashMapContext};
+
+use super::{fs_model::FileDigitalDictionary, raw_model::RawScanResultData};
+
+/// Digital dictionary compiled for scoring, see [`ScoringDictionary::update_context`]
+#[derive(Debug, Default)]
+pub struct ScoringDictionary {
+    /// data id -> (target id, weight)
+    weights: HashMap<i32, Vec<(i32, i64)>>,
+    /// target id -> threshold
+    thresholds: HashMap<i32, i64>,
+}
+
+impl ScoringDictionary {
+    /// When entries disagree on a target threshold the lowest data id wins,
+    /// validation reports the conflict
+    pub fn new(dictionary: &HashMap<i32, FileDigitalDictionary>) -> Self {
+        let mut scoring = Self::default();
+        let dictionary = dictionary.iter().collect::<BTreeMap<_, _>>();
+        for (data_id, entry) in dictionary {
+            for target in entry.contributions() {
+                scoring
+                    .weights
+                    .entry(*data_id)
+                    .or_default()
+                    .push((target.target_id, target.weight as i64));
+                scoring
+                    .thresholds
+                    .entry(target.target_id)
+                    .or_insert(target.threshold as i64);
+            }
+        }
+        scoring
+    }
+
+    /// Targets `data_id` contributes to
+    pub fn targets_of(&self, data_id: i32) -> impl Iterator<Item = i32> + '_ {
+        self.weights
+            .get(&data_id)
+            .into_iter()
+            .flatten()
+            .map(|(target_id, _)| *target_id)
+    }
+
+    /// Score every `(location, target)` over the node data, the sum of the
+    /// item weights
+    pub fn scores<'a>(&self, data: &'a [RawScanResultData]) -> BTreeMap<(&'a str, i32), i64> {
+        let mut scores = BTreeMap::<(&str, i32), i64>::new();
+        for item in data {
+            for (target_id, weight) in self.weights.get(&item.id).into_iter().flatten() {
+                *scores
+                    .entry((item.location.as_str(), *target_id))
+                    .or_default() += weight;
+            }
+        }
+        scores
+    }
+
+    /// Set for each of `scores`
+    ///
+    /// - `score_{location}_{target_id}`: the score
+    /// - `{location}{target_id}`: 1 when the score reaches the threshold,
+    ///   unset otherwise
+    pub fn update_context(
+        &self,
+        scores: &BTreeMap<(&str, i32), i64>,
+        context: &mut HashMapContext,
+    ) {
+        for (&(location, target_id), &score) in scores {
+            let _ = context.set_value(format!("score_{location}_{target_id}"), score.into());
+            if self
+                .thresholds
+                .get(&target_id)
+                .is_some_and(|threshold| score >= *threshold)
+            {
+                let _ = context.set_value(format!("{location}{target_id}"), 1.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use evalexpr::{Context, HashMapContext, Value};
+
+    use super::ScoringDictionary;
+    use crate::model::{fs_model::FileDigitalDictionary, raw_model::RawScanResultData};
+
+    fn dictionary(json: &str) -> ScoringDictionary {
+        let dictionary = serde_json::from_str::<HashMap<i32, FileDigitalDictionary>>(json).unwrap();
+        ScoringDictionary::new(&dictionary)
+    }
+
+    fn score(scoring: &ScoringDictionary, ids: &[i32]) -> HashMapContext {
+        let data = ids
+            .iter()
+            .map(|id| RawScanResultData {
+                id: *id,
+                length: 1,
+                location: "body".to_owned(),
+                offset: None,
+            })
+            .collect::<Vec<_>>();
+        let mut context = HashMapContext::new();
+        scoring.update_context(&scoring.scores(&data), &mut context);
+        context
+    }
+
+    #[test]
+    fn test_single_item_crosses() {
+        let scoring = dictionary(r#"{"101":{"target_id":9,"target_threshold":5,"value":5}}"#);
+        assert_eq!(scoring.targets_of(101).collect::<Vec<_>>(), vec![9]);
+
+        let context = score(&scoring, &[101]);
+        assert_eq!(context.get_value("score_body_9"), Some(&Value::Int(5)));
+        assert_eq!(context.get_value("body9"), Some(&Value::Int(1)));
+        assert_eq!(context.get_value("body101"), None);
+    }
+
+    #[test]
+    fn test_negative_weight_pulls_under() {
+        let scoring = dictionary(
+            r#"{
+                "101":{"targets":[{"target_id":9,"weight":3,"threshold":5}]},
+                "102":{"targets":[{"target_id":9,"weight":-2,"threshold":5}]}
+            }"#,
+        );
+        let context = score(&scoring, &[101, 101]);
+        assert_eq!(context.get_value("body9"), Some(&Value::Int(1)));
+
+        let context = score(&scoring, &[101, 101, 102]);
+        assert_eq!(context.get_value("score_body_9"), Some(&Value::Int(4)));
+        assert_eq!(context.get_value("body9"), None);
+    }
+
+    #[test]
+    fn test_targets_score_independently() {
+        let scoring = dictionary(
+            r#"{
+                "101":{"target_id":9,"target_threshold":2,"value":1,
+                       "targets":[{"target_id":10,"weight":4,"threshold":4}]},
+                "102":{"targets":[{"target_id":10,"weight":-4,"threshold":4}]}
+            }"#,
+        );
+        assert_eq!(scoring.targets_of(101).collect::<Vec<_>>(), vec![9, 10]);
+        assert_eq!(scoring.targets_of(103).count(), 0);
+
+        let context = score(&scoring, &[101, 101, 102]);
+        assert_eq!(context.get_value("score_body_9"), Some(&Value::Int(2)));
+        assert_eq!(context.get_value("body9"), Some(&Value::Int(1)));
+        assert_eq!(context.get_value("score_body_10"), Some(&Value::Int(4)));
+        assert_eq!(context.get_value("body10"), Some(&Value::Int(1)));
+
+        let context = score(&scoring, &[101, 102]);
+        assert_eq!(context.get_value("score_body_9"), Some(&Value::Int(1)));
+        assert_eq!(context.get_value("body9"), None);
+        assert_eq!(context.get_value("score_body_10"), Some(&Value::Int(0)));
+        assert_eq!(context.get_value("body10"), None);
+    }
+}
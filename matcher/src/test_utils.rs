@@ -0,0 +1,100 @@
+//! Fixtures shared by unit tests
+
+use std::{cell::RefCell, path::PathBuf, sync::Once};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+
+use crate::{
+    matcher::GlobalFileScanRule,
+    model::{agent_model::DLPFileSecurity, fs_model::FileScanRule},
+};
+
+/// Temp path unique to this test process
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}_{name}", std::process::id()))
+}
+
+/// Write `content` to [`temp_path`], the caller removes it
+pub fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+/// Rule `R{id}` of level 1 on files of any type and size
+pub fn rule(id: i32, expr: &str) -> FileScanRule {
+    serde_json::from_value(json!({
+        "id": id,
+        "code": format!("R{id}"),
+        "level": 1,
+        "file_types": [],
+        "min_file_size": 0,
+        "max_file_size": u64::MAX,
+        "check_file_encrypted": false,
+        "check_file_suffix": false,
+        "expr": expr,
+        "expr_context": {"variables": {}, "without_builtin_functions": false},
+        "md5_check": false,
+    }))
+    .unwrap()
+}
+
+/// Policy `v1` with `rules` and every other setting at its serde default
+pub fn policy(rules: Vec<FileScanRule>) -> GlobalFileScanRule {
+    let mut file_scan_rule = serde_json::from_value::<GlobalFileScanRule>(json!({
+        "config_version": "v1",
+        "file_scan_rules": [],
+        "file_digital_dictionary": {},
+    }))
+    .unwrap();
+    file_scan_rule.file_scan_rules = rules;
+    file_scan_rule
+}
+
+/// Hit of rule `R{id}` without actions, members or evidence
+pub fn hit(id: i32, level: i32) -> DLPFileSecurity {
+    DLPFileSecurity {
+        id,
+        code: format!("R{id}"),
+        level,
+        actions: Vec::new(),
+        member: None,
+        members: Vec::new(),
+        evidence: Vec::new(),
+        components: Vec::new(),
+    }
+}
+
+thread_local! {
+    /// Errors logged by this thread, tests run one per thread
+    static LOGGED_ERRORS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+struct ErrorLogger;
+
+impl Log for ErrorLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LevelFilter::Error
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            LOGGED_ERRORS.with_borrow_mut(|errors| errors.push(record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Run `f`, along with the errors it logged on this thread
+pub fn logged_errors<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let _ = log::set_logger(&ErrorLogger);
+        log::set_max_level(LevelFilter::Error);
+    });
+    LOGGED_ERRORS.with_borrow_mut(Vec::clear);
+    let result = f();
+    (result, LOGGED_ERRORS.take())
+}
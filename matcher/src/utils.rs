@@ -1,3 +1,7 @@
 pub mod common_utils;
+pub mod fuzzy_hash;
+pub mod hash_cache;
+#[cfg(not(windows))]
 pub mod mac_utils;
+#[cfg(windows)]
 pub mod win_utils;
@@ -1,33 +1,204 @@
 use std::{
-    fs::File,
-    io::copy,
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, Metadata},
+    io::{Read, Seek, SeekFrom},
     path::Path,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
+use sm3::Sm3;
 
+use super::fuzzy_hash::FuzzyHasher;
 use crate::Error;
 
-pub fn sha256_file<P: AsRef<Path>>(file_name: P) -> Result<String, Error> {
-    let mut file = File::open(file_name)?;
-    let mut hasher = Sha256::new();
-    let _ = copy(&mut file, &mut hasher)?;
-    let hash = hasher.finalize();
-    let hash_vec = hash.to_vec();
-    let hash_string = hex::encode(&hash_vec[..]);
-    Ok(hash_string)
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Digest algorithms a policy can ask for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sm3,
+    Blake3,
+    /// Fuzzy hash, see [`super::fuzzy_hash`]
+    Ssdeep,
+}
+
+impl DigestAlgorithm {
+    /// Always computed, they back `sha256_hash` and `md5_hash`
+    pub const DEFAULT: [DigestAlgorithm; 2] = [DigestAlgorithm::Sha256, DigestAlgorithm::Md5];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "md5",
+            DigestAlgorithm::Sha1 => "sha1",
+            DigestAlgorithm::Sha256 => "sha256",
+            DigestAlgorithm::Sm3 => "sm3",
+            DigestAlgorithm::Blake3 => "blake3",
+            DigestAlgorithm::Ssdeep => "ssdeep",
+        }
+    }
+
+    fn hasher(&self, total_size: u64) -> Hasher {
+        match self {
+            DigestAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            DigestAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            DigestAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            DigestAlgorithm::Sm3 => Hasher::Sm3(Sm3::new()),
+            DigestAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            DigestAlgorithm::Ssdeep => Hasher::Ssdeep(Box::new(FuzzyHasher::new(total_size))),
+        }
+    }
+}
+
+/// Digests of one file content
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileDigests {
+    pub digests: BTreeMap<DigestAlgorithm, String>,
+    /// Digests cover head + tail + size only, not the whole content
+    #[serde(default)]
+    pub partial: bool,
+    /// Bytes hashed from the head and the tail when partial, 0 otherwise
+    #[serde(default)]
+    pub partial_chunk_size: u64,
 }
 
-pub fn md5_file<P: AsRef<Path>>(file_name: P) -> Result<String, Error> {
+impl FileDigests {
+    /// Hex digest, empty when not computed
+    pub fn get(&self, algorithm: DigestAlgorithm) -> &str {
+        self.digests.get(&algorithm).map_or("", String::as_str)
+    }
+
+    pub fn contains_all(&self, algorithms: &BTreeSet<DigestAlgorithm>) -> bool {
+        algorithms
+            .iter()
+            .all(|algorithm| self.digests.contains_key(algorithm))
+    }
+}
+
+enum Hasher {
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sm3(Sm3),
+    Blake3(Box<blake3::Hasher>),
+    Ssdeep(Box<FuzzyHasher>),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sm3(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Ssdeep(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Md5(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha1(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sm3(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Ssdeep(hasher) => hasher.finalize(),
+        }
+    }
+}
+
+/// Every requested hasher, fed from one read of the file
+struct Hashers(Vec<(DigestAlgorithm, Hasher)>);
+
+impl Hashers {
+    fn new(algorithms: &BTreeSet<DigestAlgorithm>, total_size: u64) -> Self {
+        Self(
+            algorithms
+                .iter()
+                .map(|algorithm| (*algorithm, algorithm.hasher(total_size)))
+                .collect(),
+        )
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for (_, hasher) in &mut self.0 {
+            hasher.update(data);
+        }
+    }
+
+    /// Feed at most `limit` bytes of `reader`
+    fn update_from<R: Read>(&mut self, reader: R, limit: u64) -> Result<(), Error> {
+        let mut reader = reader.take(limit);
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.update(&buffer[..read]);
+        }
+    }
+
+    /// `partial_chunk_size` is none for digests over the whole content
+    fn finalize(self, partial_chunk_size: Option<u64>) -> FileDigests {
+        FileDigests {
+            digests: self
+                .0
+                .into_iter()
+                .map(|(algorithm, hasher)| (algorithm, hasher.finalize()))
+                .collect(),
+            partial: partial_chunk_size.is_some(),
+            partial_chunk_size: partial_chunk_size.unwrap_or_default(),
+        }
+    }
+}
+
+/// Compute every digest in a single read of the file
+pub fn digest_file<P: AsRef<Path>>(
+    file_name: P,
+    algorithms: &BTreeSet<DigestAlgorithm>,
+) -> Result<FileDigests, Error> {
+    let file = File::open(file_name)?;
+    let file_size = file.metadata()?.len();
+    let mut hashers = Hashers::new(algorithms, file_size);
+    hashers.update_from(file, u64::MAX)?;
+    Ok(hashers.finalize(None))
+}
+
+/// Digest of the first and last `chunk_size` bytes plus the file size,
+/// for files too large to hash in full. Fuzzy hashes are left out, they
+/// only make sense over the whole content.
+pub fn digest_file_partial<P: AsRef<Path>>(
+    file_name: P,
+    algorithms: &BTreeSet<DigestAlgorithm>,
+    chunk_size: u64,
+) -> Result<FileDigests, Error> {
     let mut file = File::open(file_name)?;
-    let mut hasher = Md5::new();
-    let _ = copy(&mut file, &mut hasher)?;
-    let hash = hasher.finalize();
-    let hash_vec = hash.to_vec();
-    let hash_string = hex::encode(&hash_vec[..]);
-    Ok(hash_string)
+    let file_size = file.metadata()?.len();
+    let algorithms = algorithms
+        .iter()
+        .filter(|algorithm| **algorithm != DigestAlgorithm::Ssdeep)
+        .copied()
+        .collect();
+    let mut hashers = Hashers::new(&algorithms, file_size);
+    hashers.update_from(&mut file, chunk_size)?;
+    let tail_start = file_size.saturating_sub(chunk_size).max(chunk_size);
+    if tail_start < file_size {
+        file.seek(SeekFrom::Start(tail_start))?;
+        hashers.update_from(&mut file, chunk_size)?;
+    }
+    hashers.update(&file_size.to_le_bytes());
+    Ok(hashers.finalize(Some(chunk_size)))
 }
 
 pub fn system_time_to_unix_time(tm: SystemTime) -> i64 {
@@ -35,3 +206,121 @@ pub fn system_time_to_unix_time(tm: SystemTime) -> i64 {
         .ok()
         .map_or(0, |t| t.as_secs() as i64)
 }
+
+/// Modify time in nanoseconds, precise enough to notice in-place rewrites
+pub fn modified_nanos(md: &Metadata) -> u64 {
+    md.modified()
+        .ok()
+        .and_then(|tm| tm.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |t| t.as_nanos() as u64)
+}
+
+pub fn file_inode(md: &Metadata) -> u64 {
+    #[cfg(windows)]
+    {
+        super::win_utils::file_inode(md)
+    }
+    #[cfg(not(windows))]
+    {
+        super::mac_utils::file_inode(md)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{digest_file, digest_file_partial, DigestAlgorithm};
+    use crate::test_utils::temp_file;
+
+    #[test]
+    fn test_known_digests() {
+        let path = temp_file("known_digests", b"abc");
+        let algorithms = BTreeSet::from([
+            DigestAlgorithm::Md5,
+            DigestAlgorithm::Sha1,
+            DigestAlgorithm::Sha256,
+            DigestAlgorithm::Sm3,
+            DigestAlgorithm::Blake3,
+        ]);
+        let digests = digest_file(&path, &algorithms).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(!digests.partial);
+        assert_eq!(
+            digests.get(DigestAlgorithm::Md5),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            digests.get(DigestAlgorithm::Sha1),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            digests.get(DigestAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            digests.get(DigestAlgorithm::Sm3),
+            "66c7f0f462eeedd9d1f2d46bdc10e4e24167c4875cf2f7a2297da02b8f4ba8e0"
+        );
+        assert_eq!(
+            digests.get(DigestAlgorithm::Blake3),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(digests.get(DigestAlgorithm::Ssdeep), "");
+    }
+
+    #[test]
+    fn test_partial_digests() {
+        let algorithms = BTreeSet::from([
+            DigestAlgorithm::Md5,
+            DigestAlgorithm::Sha256,
+            DigestAlgorithm::Ssdeep,
+        ]);
+        // (content, digests of head + tail + size as u64 le) with 3 byte chunks
+        let cases: [(&[u8], &str, &str); 3] = [
+            (
+                b"0123456789",
+                "3729e43a667db0a968c1236248e7b1846c16f4446bb07e7a7dcef230606ce426",
+                "4faa896c38223d9504b2e5cd61a0dd91",
+            ),
+            // the tail starts after the head, never overlaps it
+            (
+                b"01234",
+                "8f63ed5a990f099d1cfe0522b6ac6eb010fea216220bbfdf1951dd921f5bb6d7",
+                "6c1998bc734e7c3c19311eec15bd4a2c",
+            ),
+            // no tail past a short head
+            (
+                b"ab",
+                "87eadf2256857fb04128d6ed2e298990f99ee45bbbe95c8a96d7fe707599dc30",
+                "42b27147e9ca116458acf2a9e7e3e68f",
+            ),
+        ];
+        for (content, sha256, md5) in cases {
+            let path = temp_file("partial_digests", content);
+            let digests = digest_file_partial(&path, &algorithms, 3).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert!(digests.partial);
+            assert_eq!(digests.get(DigestAlgorithm::Sha256), sha256);
+            assert_eq!(digests.get(DigestAlgorithm::Md5), md5);
+            assert!(!digests.digests.contains_key(&DigestAlgorithm::Ssdeep));
+        }
+    }
+
+    #[test]
+    fn test_partial_ignores_middle() {
+        let algorithms = BTreeSet::from([DigestAlgorithm::Sha256]);
+        let digest = |name: &str, content: &[u8]| {
+            let path = temp_file(name, content);
+            let digests = digest_file_partial(&path, &algorithms, 3).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            digests
+        };
+        let digests = digest("partial_a", b"012xxxx789");
+        assert_eq!(digests, digest("partial_b", b"012yyyy789"));
+        assert_ne!(digests, digest("partial_c", b"012yyyyy789"));
+        assert_ne!(digests, digest("partial_d", b"012xxxx788"));
+    }
+}
@@ -0,0 +1,387 @@
+//! Context triggered piecewise hashing, producing ssdeep style digests.
+
+const ROLLING_WINDOW: usize = 7;
+const MIN_BLOCKSIZE: u64 = 3;
+const SPAMSUM_LENGTH: usize = 64;
+const NUM_BLOCKHASHES: usize = 31;
+const HASH_PRIME: u32 = 0x0100_0193;
+const HASH_INIT: u32 = 0x2802_1967;
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn block_size(index: usize) -> u64 {
+    MIN_BLOCKSIZE << index
+}
+
+fn sum_hash(c: u8, h: u32) -> u32 {
+    h.wrapping_mul(HASH_PRIME) ^ c as u32
+}
+
+#[derive(Default)]
+struct RollState {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl RollState {
+    fn roll(&mut self, c: u8) {
+        self.h2 = self.h2.wrapping_sub(self.h1);
+        self.h2 = self.h2.wrapping_add(ROLLING_WINDOW as u32 * c as u32);
+        self.h1 = self.h1.wrapping_add(c as u32);
+        self.h1 = self.h1.wrapping_sub(self.window[self.n] as u32);
+        self.window[self.n] = c;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+        self.h3 = (self.h3 << 5) ^ c as u32;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+#[derive(Clone)]
+struct BlockHash {
+    h: u32,
+    half_h: u32,
+    digest: Vec<u8>,
+    /// Character pending at the end of `digest`, emitted once it is full
+    tail: Option<u8>,
+    half_tail: Option<u8>,
+}
+
+impl BlockHash {
+    fn new(h: u32, half_h: u32) -> Self {
+        Self {
+            h,
+            half_h,
+            digest: Vec::with_capacity(SPAMSUM_LENGTH),
+            tail: None,
+            half_tail: None,
+        }
+    }
+}
+
+/// Streaming fuzzy hasher, tracking every candidate block size in one pass
+pub struct FuzzyHasher {
+    roll: RollState,
+    blocks: Vec<BlockHash>,
+    start: usize,
+    total_size: u64,
+    last_h: Option<u32>,
+}
+
+impl FuzzyHasher {
+    /// `total_size` is the length of the whole input, used to pick the block size
+    pub fn new(total_size: u64) -> Self {
+        Self {
+            roll: RollState::default(),
+            blocks: vec![BlockHash::new(HASH_INIT, HASH_INIT)],
+            start: 0,
+            total_size,
+            last_h: None,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for c in data {
+            self.step(*c);
+        }
+    }
+
+    fn step(&mut self, c: u8) {
+        self.roll.roll(c);
+        let h = self.roll.sum() as u64;
+
+        for block in &mut self.blocks[self.start..] {
+            block.h = sum_hash(c, block.h);
+            block.half_h = sum_hash(c, block.half_h);
+        }
+        if let Some(ref mut last_h) = self.last_h {
+            *last_h = sum_hash(c, *last_h);
+        }
+
+        // `fork` may add a block during the loop, it is checked on this step too
+        let mut index = self.start;
+        while index < self.blocks.len() {
+            // a reset point for one block size is also one for all smaller sizes
+            if h % block_size(index) != block_size(index) - 1 {
+                break;
+            }
+            if self.blocks[index].digest.is_empty() {
+                self.fork();
+            }
+
+            let block = &mut self.blocks[index];
+            block.tail = Some(B64[(block.h % 64) as usize]);
+            block.half_tail = Some(B64[(block.half_h % 64) as usize]);
+            if block.digest.len() < SPAMSUM_LENGTH - 1 {
+                block.digest.push(B64[(block.h % 64) as usize]);
+                block.tail = None;
+                block.h = HASH_INIT;
+                if block.digest.len() < SPAMSUM_LENGTH / 2 {
+                    block.half_h = HASH_INIT;
+                    block.half_tail = None;
+                }
+            } else {
+                self.reduce();
+            }
+            index += 1;
+        }
+    }
+
+    fn fork(&mut self) {
+        let last = &self.blocks[self.blocks.len() - 1];
+        if self.blocks.len() < NUM_BLOCKHASHES {
+            let block = BlockHash::new(last.h, last.half_h);
+            self.blocks.push(block);
+        } else if self.last_h.is_none() {
+            self.last_h = Some(last.h);
+        }
+    }
+
+    fn reduce(&mut self) {
+        if self.blocks.len() - self.start < 2
+            || block_size(self.start) * SPAMSUM_LENGTH as u64 >= self.total_size
+            || self.blocks[self.start + 1].digest.len() < SPAMSUM_LENGTH / 2
+        {
+            return;
+        }
+        self.start += 1;
+    }
+
+    /// Digest in the `blocksize:hash:hash2` form
+    pub fn finalize(self) -> String {
+        let h = self.roll.sum();
+        let mut index = self.start;
+        while block_size(index) * (SPAMSUM_LENGTH as u64) < self.total_size {
+            index += 1;
+            if index >= NUM_BLOCKHASHES {
+                return String::new();
+            }
+        }
+        while index >= self.blocks.len() {
+            index -= 1;
+        }
+        while index > self.start && self.blocks[index].digest.len() < SPAMSUM_LENGTH / 2 {
+            index -= 1;
+        }
+
+        let block = &self.blocks[index];
+        let mut first = block.digest.clone();
+        if h != 0 {
+            first.push(B64[(block.h % 64) as usize]);
+        } else if let Some(tail) = block.tail {
+            first.push(tail);
+        }
+
+        let mut second = Vec::new();
+        if index + 1 < self.blocks.len() {
+            let next = &self.blocks[index + 1];
+            second.extend_from_slice(&next.digest[..next.digest.len().min(SPAMSUM_LENGTH / 2 - 1)]);
+            if h != 0 {
+                second.push(B64[(next.half_h % 64) as usize]);
+            } else if let Some(half_tail) = next.half_tail {
+                second.push(half_tail);
+            }
+        } else if h != 0 {
+            let h = if index == 0 {
+                block.h
+            } else {
+                self.last_h.unwrap_or(block.h)
+            };
+            second.push(B64[(h % 64) as usize]);
+        }
+
+        format!(
+            "{}:{}:{}",
+            block_size(index),
+            String::from_utf8_lossy(&first),
+            String::from_utf8_lossy(&second)
+        )
+    }
+}
+
+/// Similarity of two digests, from 0 (unrelated) to 100 (identical)
+pub fn compare(left: &str, right: &str) -> u32 {
+    let (Some((left_size, left_first, left_second)), Some((right_size, right_first, right_second))) =
+        (parse(left), parse(right))
+    else {
+        return 0;
+    };
+
+    // sizes come from rule text, doubling them must not overflow
+    let is_double = |size: u64, half: u64| half.checked_mul(2) == Some(size);
+    if left_size != right_size
+        && !is_double(left_size, right_size)
+        && !is_double(right_size, left_size)
+    {
+        return 0;
+    }
+
+    let left_first = eliminate_sequences(left_first);
+    let left_second = eliminate_sequences(left_second);
+    let right_first = eliminate_sequences(right_first);
+    let right_second = eliminate_sequences(right_second);
+
+    if left_size == right_size && left_first == right_first && left_second == right_second {
+        return 100;
+    }
+
+    if left_size == right_size {
+        score_strings(&left_first, &right_first, left_size).max(score_strings(
+            &left_second,
+            &right_second,
+            left_size.saturating_mul(2),
+        ))
+    } else if is_double(left_size, right_size) {
+        score_strings(&left_first, &right_second, left_size)
+    } else {
+        score_strings(&left_second, &right_first, right_size)
+    }
+}
+
+fn parse(digest: &str) -> Option<(u64, &[u8], &[u8])> {
+    let mut parts = digest.splitn(3, ':');
+    let size = parts.next()?.parse::<u64>().ok()?;
+    let first = parts.next()?;
+    let second = parts.next()?.split(',').next().unwrap_or_default();
+    Some((size, first.as_bytes(), second.as_bytes()))
+}
+
+/// Runs of more than three identical characters carry little information
+fn eliminate_sequences(digest: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(digest.len());
+    for (i, c) in digest.iter().enumerate() {
+        if i < 3 || *c != digest[i - 1] || *c != digest[i - 2] || *c != digest[i - 3] {
+            result.push(*c);
+        }
+    }
+    result
+}
+
+fn has_common_substring(left: &[u8], right: &[u8]) -> bool {
+    left.len() >= ROLLING_WINDOW
+        && right.len() >= ROLLING_WINDOW
+        && left
+            .windows(ROLLING_WINDOW)
+            .any(|window| right.windows(ROLLING_WINDOW).any(|other| other == window))
+}
+
+/// Edit distance with insert and delete costing 1, substitution 2
+fn edit_distance(left: &[u8], right: &[u8]) -> usize {
+    let mut previous = (0..=right.len()).collect::<Vec<_>>();
+    let mut current = vec![0; right.len() + 1];
+    for (i, l) in left.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, r) in right.iter().enumerate() {
+            let substitute = previous[j] + if l == r { 0 } else { 2 };
+            current[j + 1] = substitute.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[right.len()]
+}
+
+fn score_strings(left: &[u8], right: &[u8], block_size: u64) -> u32 {
+    if left.len() > SPAMSUM_LENGTH
+        || right.len() > SPAMSUM_LENGTH
+        || !has_common_substring(left, right)
+    {
+        return 0;
+    }
+
+    let distance = edit_distance(left, right) * SPAMSUM_LENGTH / (left.len() + right.len());
+    let distance = (100 * distance / SPAMSUM_LENGTH) as u32;
+    if distance >= 100 {
+        return 0;
+    }
+    let score = 100 - distance;
+
+    // small block sizes can't back a high score with few characters
+    let cap_size = (99 + ROLLING_WINDOW as u64) / ROLLING_WINDOW as u64 * MIN_BLOCKSIZE;
+    if block_size >= cap_size {
+        return score;
+    }
+    let cap = (block_size / MIN_BLOCKSIZE) as u32 * left.len().min(right.len()) as u32;
+    score.min(cap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare, FuzzyHasher};
+
+    fn sample_text(seed: u32, len: usize) -> Vec<u8> {
+        let words = [
+            "salary", "contract", "id", "bank", "account", "name", "phone", "address",
+        ];
+        let mut state = seed;
+        let mut text = Vec::with_capacity(len);
+        while text.len() < len {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            text.extend_from_slice(words[(state >> 16) as usize % words.len()].as_bytes());
+            text.push(b' ');
+        }
+        text.truncate(len);
+        text
+    }
+
+    fn fuzzy_hash(data: &[u8]) -> String {
+        let mut hasher = FuzzyHasher::new(data.len() as u64);
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn test_reference_digests() {
+        // digests and score produced by ssdeep 2.x
+        let hash1 =
+            fuzzy_hash(b"Also called fuzzy hashes, Ctph can match inputs that have homologies.");
+        let hash2 =
+            fuzzy_hash(b"Also called fuzzy hashes, CTPH can match inputs that have homologies.");
+        assert_eq!(hash1, "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C");
+        assert_eq!(hash2, "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2C");
+        assert_eq!(compare(&hash1, &hash2), 22);
+        assert_eq!(fuzzy_hash(b""), "3::");
+    }
+
+    #[test]
+    fn test_edited_copy_is_similar() {
+        let original = sample_text(7, 20_000);
+        let mut edited = original.clone();
+        edited[10_000] = b'#';
+        let unrelated = sample_text(42, 20_000);
+
+        let original_hash = fuzzy_hash(&original);
+        assert_eq!(compare(&original_hash, &original_hash), 100);
+        assert!(compare(&original_hash, &fuzzy_hash(&edited)) >= 80);
+        assert_eq!(compare(&original_hash, &fuzzy_hash(&unrelated)), 0);
+    }
+
+    #[test]
+    fn test_huge_block_sizes() {
+        let hash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+        for huge in [
+            "18446744073709551615:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+            "9223372036854775808:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+        ] {
+            assert_eq!(compare(huge, hash), 0);
+            assert_eq!(compare(hash, huge), 0);
+            assert_eq!(compare(huge, huge), 100);
+        }
+        let huge = "18446744073709551615:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+        let edited = "18446744073709551615:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2C";
+        assert!(compare(huge, edited) <= 100);
+        assert_eq!(
+            compare(
+                "9223372036854775807:abc:def",
+                "18446744073709551614:abc:def"
+            ),
+            compare(
+                "18446744073709551614:abc:def",
+                "9223372036854775807:abc:def"
+            )
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, Metadata},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::common_utils::{file_inode, modified_nanos, FileDigests};
+use crate::Error;
+
+/// Default number of files remembered by a hash cache
+pub const DEFAULT_HASH_CACHE_CAPACITY: usize = 4096;
+
+/// Identity of a file content, as far as its metadata can tell
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct FileKey {
+    pub path: String,
+    pub size: u64,
+    pub mtime: u64,
+    pub inode: u64,
+}
+
+impl FileKey {
+    pub fn new(path: &Path, md: &Metadata) -> Self {
+        Self {
+            path: path.to_string_lossy().into_owned(),
+            size: md.len(),
+            mtime: modified_nanos(md),
+            inode: file_inode(md),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CacheRecord {
+    key: FileKey,
+    digests: FileDigests,
+}
+
+/// Bounded file digest cache, the oldest entries are evicted first
+pub struct HashCache {
+    capacity: usize,
+    entries: HashMap<FileKey, FileDigests>,
+    order: VecDeque<FileKey>,
+    persist_path: Option<PathBuf>,
+    dirty: bool,
+}
+
+impl Default for HashCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_CACHE_CAPACITY)
+    }
+}
+
+impl HashCache {
+    /// In-memory cache, a `capacity` of 0 disables caching
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            persist_path: None,
+            dirty: false,
+        }
+    }
+
+    /// Cache persisted to `persist_path`, starting from the entries saved there
+    pub fn with_persist_path(capacity: usize, persist_path: PathBuf) -> Self {
+        let mut cache = Self::new(capacity);
+        if persist_path.exists() {
+            match Self::read_records(&persist_path) {
+                Ok(records) => {
+                    for record in records {
+                        cache.insert(record.key, record.digests);
+                    }
+                    info!(
+                        "[HashCache] load {} entries from {}",
+                        cache.entries.len(),
+                        persist_path.display()
+                    );
+                }
+                Err(e) => warn!(
+                    "[HashCache] Failed to load {}, start empty: {e}",
+                    persist_path.display()
+                ),
+            }
+        }
+        cache.persist_path = Some(persist_path);
+        cache.dirty = false;
+        cache
+    }
+
+    pub fn get(&self, key: &FileKey) -> Option<&FileDigests> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: FileKey, digests: FileDigests) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), digests).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Write the cache to its persist path, if any and if it changed
+    pub fn save(&mut self) -> Result<(), Error> {
+        let Some(ref persist_path) = self.persist_path else {
+            return Ok(());
+        };
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let records = self
+            .order
+            .iter()
+            .filter_map(|key| {
+                self.entries.get(key).map(|digests| CacheRecord {
+                    key: key.clone(),
+                    digests: digests.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        // write aside then rename, a crash never leaves a truncated cache
+        let temp_path = persist_path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &records)?;
+        writer.flush()?;
+        fs::rename(&temp_path, persist_path)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn read_records(persist_path: &Path) -> Result<Vec<CacheRecord>, Error> {
+        let reader = BufReader::new(File::open(persist_path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{FileKey, HashCache};
+    use crate::{
+        test_utils::{temp_file, temp_path},
+        utils::common_utils::{DigestAlgorithm, FileDigests},
+    };
+
+    fn digests(sha256: &str) -> FileDigests {
+        FileDigests {
+            digests: BTreeMap::from([(DigestAlgorithm::Sha256, sha256.to_owned())]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_key_changes_invalidate() {
+        let path = temp_file("hash_cache_key", b"first");
+        let key = FileKey::new(&path, &path.metadata().unwrap());
+        let mut cache = HashCache::new(8);
+        cache.insert(key.clone(), digests("a"));
+        assert_eq!(
+            cache.get(&FileKey::new(&path, &path.metadata().unwrap())),
+            Some(&digests("a"))
+        );
+
+        // same size, only the mtime moves
+        std::fs::write(&path, b"other").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(cache
+            .get(&FileKey::new(&path, &path.metadata().unwrap()))
+            .is_none());
+        std::fs::remove_file(&path).unwrap();
+
+        for changed in [
+            FileKey {
+                size: key.size + 1,
+                ..key.clone()
+            },
+            FileKey {
+                inode: key.inode + 1,
+                ..key.clone()
+            },
+        ] {
+            assert!(cache.get(&changed).is_none());
+        }
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest() {
+        let key = |n: u64| FileKey {
+            path: format!("/tmp/{n}"),
+            size: n,
+            mtime: n,
+            inode: n,
+        };
+        let mut cache = HashCache::new(2);
+        for n in 0..3 {
+            cache.insert(key(n), digests(&n.to_string()));
+        }
+        assert!(cache.get(&key(0)).is_none());
+        assert_eq!(cache.get(&key(2)), Some(&digests("2")));
+
+        let mut disabled = HashCache::new(0);
+        disabled.insert(key(0), digests("0"));
+        assert!(disabled.get(&key(0)).is_none());
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let persist_path = temp_path("hash_cache.json");
+        let _ = std::fs::remove_file(&persist_path);
+        let key = FileKey {
+            path: "/tmp/doc.txt".to_owned(),
+            size: 3,
+            mtime: 42,
+            inode: 7,
+        };
+        let mut cache = HashCache::with_persist_path(8, persist_path.clone());
+        cache.insert(key.clone(), digests("abc"));
+        cache.save().unwrap();
+
+        let loaded = HashCache::with_persist_path(8, persist_path.clone());
+        assert_eq!(loaded.get(&key), Some(&digests("abc")));
+        std::fs::remove_file(&persist_path).unwrap();
+    }
+}
@@ -0,0 +1,5 @@
+use std::{fs::Metadata, os::unix::fs::MetadataExt};
+
+pub fn file_inode(md: &Metadata) -> u64 {
+    md.ino()
+}
@@ -0,0 +1,727 @@
+use std::collections::{BTreeMap, HashSet};
+
+use evalexpr::{build_operator_tree, Node, Operator, Value as ExprValue};
+use log::warn;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_path_to_error::{Path, Segment};
+
+use crate::{
+    fs_error::{Error, PolicyDiagnostic},
+    matcher::{GlobalFileScanFormat, GlobalFileScanRule},
+    model::{
+        fs_model::{PolicyCondition, RuleAction, Suppression},
+        functions::build_glob,
+        schedule::{check_hour_range, parse_time, parse_timezone, parse_weekday},
+    },
+};
+
+const RULE_DOCUMENT: &str = "rule";
+const FORMAT_DOCUMENT: &str = "format";
+const SUPPRESSION_DOCUMENT: &str = "suppression";
+
+/// Parse and validate a rule document, reporting every problem found
+pub fn load_file_scan_rule(str_file_scan_rule: &str) -> Result<GlobalFileScanRule, Error> {
+    let mut file_scan_rule =
+        deserialize_document::<GlobalFileScanRule>(RULE_DOCUMENT, str_file_scan_rule)?;
+    let mut diagnostics = file_scan_rule
+        .document_registry
+        .load_files()
+        .into_iter()
+        .map(|(position, file, e)| PolicyDiagnostic {
+            document: RULE_DOCUMENT,
+            path: format!("$.document_registry.files[{position}]"),
+            rule_id: None,
+            reason: format!("failed to load {file}: {e}"),
+        })
+        .collect::<Vec<_>>();
+    diagnostics.extend(validate_file_scan_rule(&file_scan_rule));
+    if diagnostics.is_empty() {
+        Ok(file_scan_rule)
+    } else {
+        Err(Error::Policy(diagnostics))
+    }
+}
+
+pub fn load_file_scan_format(str_file_scan_format: &str) -> Result<GlobalFileScanFormat, Error> {
+    deserialize_document(FORMAT_DOCUMENT, str_file_scan_format)
+}
+
+/// Parse and validate a suppression added at runtime
+pub fn load_suppression(str_suppression: &str) -> Result<Suppression, Error> {
+    let suppression = deserialize_document::<Suppression>(SUPPRESSION_DOCUMENT, str_suppression)?;
+    let mut diagnostics = Vec::new();
+    validate_suppression(&suppression, |field, reason| {
+        diagnostics.push(PolicyDiagnostic {
+            document: SUPPRESSION_DOCUMENT,
+            path: format!("$.{field}"),
+            rule_id: None,
+            reason,
+        })
+    });
+    if diagnostics.is_empty() {
+        Ok(suppression)
+    } else {
+        Err(Error::Policy(diagnostics))
+    }
+}
+
+fn deserialize_document<T: DeserializeOwned>(
+    document: &'static str,
+    input: &str,
+) -> Result<T, Error> {
+    let value = serde_json::from_str::<Value>(input).map_err(|e| {
+        Error::Policy(vec![PolicyDiagnostic {
+            document,
+            path: "$".to_owned(),
+            rule_id: None,
+            reason: e.to_string(),
+        }])
+    })?;
+    serde_path_to_error::deserialize(&value).map_err(|e| {
+        Error::Policy(vec![PolicyDiagnostic {
+            document,
+            path: json_path(e.path()),
+            rule_id: rule_id_at(&value, e.path()),
+            reason: e.inner().to_string(),
+        }])
+    })
+}
+
+fn json_path(path: &Path) -> String {
+    path.iter().fold("$".to_owned(), |mut json_path, segment| {
+        match segment {
+            Segment::Seq { index } => json_path.push_str(&format!("[{index}]")),
+            Segment::Map { key } => json_path.push_str(&format!(".{key}")),
+            Segment::Enum { variant } => json_path.push_str(&format!(".{variant}")),
+            Segment::Unknown => json_path.push_str(".?"),
+        }
+        json_path
+    })
+}
+
+/// Id of the rule that contains `path`, if `path` points inside `file_scan_rules`
+fn rule_id_at(value: &Value, path: &Path) -> Option<i32> {
+    let mut segments = path.iter();
+    match (segments.next(), segments.next()) {
+        (Some(Segment::Map { key }), Some(Segment::Seq { index })) if key == "file_scan_rules" => {
+            value[key.as_str()][*index]["id"]
+                .as_i64()
+                .map(|id| id as i32)
+        }
+        _ => None,
+    }
+}
+
+fn validate_file_scan_rule(file_scan_rule: &GlobalFileScanRule) -> Vec<PolicyDiagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut rule_ids = HashSet::new();
+    let mut expr_variables = HashSet::new();
+    let mut scored_targets = HashSet::new();
+
+    for (index, rule) in file_scan_rule.file_scan_rules.iter().enumerate() {
+        let mut report = |field: &str, reason: String| {
+            diagnostics.push(PolicyDiagnostic {
+                document: RULE_DOCUMENT,
+                path: format!("$.file_scan_rules[{index}].{field}"),
+                rule_id: Some(rule.id),
+                reason,
+            })
+        };
+
+        if !rule_ids.insert(rule.id) {
+            report("id", format!("duplicate rule id {}", rule.id));
+        }
+
+        if rule.code.trim().is_empty() {
+            report("code", "empty code".to_owned());
+        }
+
+        if rule.min_file_size > rule.max_file_size {
+            report(
+                "min_file_size",
+                format!(
+                    "min_file_size {} > max_file_size {}",
+                    rule.min_file_size, rule.max_file_size
+                ),
+            );
+        }
+
+        let mut valid = [None, None];
+        for (bound, (field, time)) in [
+            ("valid_from", &rule.valid_from),
+            ("valid_until", &rule.valid_until),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            match parse_time(time) {
+                Ok(time) => valid[bound] = time,
+                Err(e) => report(field, e),
+            }
+        }
+        if let [Some(from), Some(until)] = valid {
+            if from >= until {
+                report(
+                    "valid_from",
+                    format!("valid_from {from} not before valid_until {until}"),
+                );
+            }
+        }
+
+        if let Some(schedule) = &rule.schedule {
+            for (position, day) in schedule.weekdays.iter().enumerate() {
+                if let Err(e) = parse_weekday(day) {
+                    report(&format!("schedule.weekdays[{position}]"), e);
+                }
+            }
+            for (position, range) in schedule.hours.iter().enumerate() {
+                if let Err(e) = check_hour_range(range) {
+                    report(&format!("schedule.hours[{position}]"), e);
+                }
+            }
+            if let Err(e) = parse_timezone(&schedule.timezone) {
+                report("schedule.timezone", e);
+            }
+        }
+
+        for position in duplicate_positions(&rule.actions) {
+            report(
+                &format!("actions[{position}]"),
+                format!("duplicate action {}", rule.actions[position].name()),
+            );
+        }
+
+        for (kind, patterns) in [
+            ("include", &rule.path_filter.include),
+            ("exclude", &rule.path_filter.exclude),
+        ] {
+            let globs = [
+                ("path_globs", &patterns.path_globs),
+                ("name_globs", &patterns.name_globs),
+            ];
+            for (field, globs) in globs {
+                for (position, glob) in globs.iter().enumerate() {
+                    if let Err(e) = build_glob(glob) {
+                        report(
+                            &format!("path_filter.{kind}.{field}[{position}]"),
+                            format!("bad glob: {e}"),
+                        );
+                    }
+                }
+            }
+            let regexes = [
+                ("path_regex", &patterns.path_regex),
+                ("name_regex", &patterns.name_regex),
+            ];
+            for (field, regexes) in regexes {
+                for (position, regex) in regexes.iter().enumerate() {
+                    if let Err(e) = Regex::new(regex) {
+                        report(
+                            &format!("path_filter.{kind}.{field}[{position}]"),
+                            format!("bad regex: {e}"),
+                        );
+                    }
+                }
+            }
+        }
+
+        if !rule.expr.is_empty() {
+            match build_operator_tree(&rule.expr) {
+                Ok(node) => {
+                    expr_variables.extend(
+                        node.iter_variable_identifiers()
+                            .map(ToOwned::to_owned)
+                            .collect::<Vec<_>>(),
+                    );
+                    scored_targets.extend(score_targets(&node));
+                }
+                Err(e) => report("expr", format!("bad expr syntax: {e}")),
+            }
+        }
+    }
+
+    let hash_settings = &file_scan_rule.hash_settings;
+    if hash_settings.partial_hash && hash_settings.partial_chunk_size == 0 {
+        diagnostics.push(PolicyDiagnostic {
+            document: RULE_DOCUMENT,
+            path: "$.hash_settings.partial_chunk_size".to_owned(),
+            rule_id: None,
+            reason: "partial_chunk_size must be positive when partial_hash is on".to_owned(),
+        });
+    }
+
+    let precedence = &file_scan_rule.verdict.precedence;
+    for position in duplicate_positions(precedence) {
+        diagnostics.push(PolicyDiagnostic {
+            document: RULE_DOCUMENT,
+            path: format!("$.verdict.precedence[{position}]"),
+            rule_id: None,
+            reason: format!("duplicate action {}", precedence[position].name()),
+        });
+    }
+
+    for (index, suppression) in file_scan_rule.suppressions.iter().enumerate() {
+        validate_suppression(suppression, |field, reason| {
+            diagnostics.push(PolicyDiagnostic {
+                document: RULE_DOCUMENT,
+                path: format!("$.suppressions[{index}].{field}"),
+                rule_id: None,
+                reason,
+            })
+        });
+    }
+
+    let mut labels = HashSet::new();
+    for (index, set) in file_scan_rule.document_registry.sets.iter().enumerate() {
+        let mut report = |field: &str, reason: String| {
+            diagnostics.push(PolicyDiagnostic {
+                document: RULE_DOCUMENT,
+                path: format!("$.document_registry.sets[{index}].{field}"),
+                rule_id: set.hit.as_ref().map(|hit| hit.id),
+                reason,
+            })
+        };
+
+        if set.label.trim().is_empty() {
+            report("label", "empty label".to_owned());
+        } else if !labels.insert(set.label.as_str()) {
+            report("label", format!("duplicate label {}", set.label));
+        }
+
+        for (field, digests, len) in [("sha256", &set.sha256, 64), ("md5", &set.md5, 32)] {
+            for (position, digest) in digests.iter().enumerate() {
+                let digest = digest.trim();
+                if digest.len() != len || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    report(
+                        &format!("{field}[{position}]"),
+                        format!("malformed {field} digest {digest}"),
+                    );
+                }
+            }
+        }
+
+        if let Some(hit) = &set.hit {
+            if !rule_ids.insert(hit.id) {
+                report("hit.id", format!("duplicate rule id {}", hit.id));
+            }
+            for position in duplicate_positions(&hit.actions) {
+                report(
+                    &format!("hit.actions[{position}]"),
+                    format!("duplicate action {}", hit.actions[position].name()),
+                );
+            }
+        }
+    }
+
+    let mut component_ids = HashSet::new();
+    let mut composite_ids = HashSet::new();
+    for (index, composite) in file_scan_rule.composite_policies.iter().enumerate() {
+        let mut report = |field: &str, reason: String| {
+            diagnostics.push(PolicyDiagnostic {
+                document: RULE_DOCUMENT,
+                path: format!("$.composite_policies[{index}].{field}"),
+                rule_id: Some(composite.id),
+                reason,
+            })
+        };
+
+        if rule_ids.contains(&composite.id) || !composite_ids.insert(composite.id) {
+            report("id", format!("duplicate rule id {}", composite.id));
+        }
+        if composite.code.trim().is_empty() {
+            report("code", "empty code".to_owned());
+        }
+        for position in duplicate_positions(&composite.actions) {
+            report(
+                &format!("actions[{position}]"),
+                format!("duplicate action {}", composite.actions[position].name()),
+            );
+        }
+        validate_condition(&composite.condition, "condition", &rule_ids, &mut report);
+        // e.g. a `not` alone, it would hit every file without the rule
+        if composite.condition.is_met(&|_| false) {
+            report(
+                "condition",
+                "condition holds without any hit, combine a `not` with a rule".to_owned(),
+            );
+        }
+        component_ids.extend(composite.condition.rule_ids().into_iter().map(|(id, _)| id));
+    }
+
+    for (index, rule) in file_scan_rule.file_scan_rules.iter().enumerate() {
+        if rule.component_only && !component_ids.contains(&rule.id) {
+            diagnostics.push(PolicyDiagnostic {
+                document: RULE_DOCUMENT,
+                path: format!("$.file_scan_rules[{index}].component_only"),
+                rule_id: Some(rule.id),
+                reason: format!("component only rule {} feeds no composite policy", rule.id),
+            });
+        }
+    }
+
+    let dictionary = file_scan_rule
+        .file_digital_dictionary
+        .iter()
+        .collect::<BTreeMap<_, _>>();
+    let mut thresholds = BTreeMap::new();
+    for (data_id, entry) in dictionary {
+        let mut report = |field: String, reason: String| {
+            diagnostics.push(PolicyDiagnostic {
+                document: RULE_DOCUMENT,
+                path: format!("$.file_digital_dictionary.{data_id}{field}"),
+                rule_id: None,
+                reason,
+            })
+        };
+
+        if entry.target_id.is_none() && entry.targets.is_empty() {
+            report(String::new(), "no dictionary target".to_owned());
+        }
+
+        let fields = entry
+            .target_id
+            .map(|_| ".target_threshold".to_owned())
+            .into_iter()
+            .chain(
+                (0..entry.targets.len()).map(|position| format!(".targets[{position}].threshold")),
+            );
+        for (threshold_field, target) in fields.zip(entry.contributions()) {
+            // may be meant for rules of a later policy, so not an error
+            if !scored_targets.contains(&(target.target_id as i64))
+                && !is_target_referenced(&expr_variables, target.target_id)
+            {
+                warn!(
+                    "[Policy] Dictionary target {} of data id {data_id} is not referenced by any rule expr",
+                    target.target_id
+                );
+            }
+            let (first_id, threshold) = *thresholds
+                .entry(target.target_id)
+                .or_insert((*data_id, target.threshold));
+            if threshold != target.threshold {
+                report(
+                    threshold_field,
+                    format!(
+                        "threshold {} of target {} conflicts with {threshold} set by data id {first_id}",
+                        target.threshold, target.target_id
+                    ),
+                );
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Check a composite condition at `path`, `rule_ids` are those of rules and
+/// registry hits
+fn validate_condition(
+    condition: &PolicyCondition,
+    path: &str,
+    rule_ids: &HashSet<i32>,
+    report: &mut impl FnMut(&str, String),
+) {
+    match condition {
+        PolicyCondition::Rule(id) => {
+            if !rule_ids.contains(id) {
+                report(&format!("{path}.rule"), format!("unknown rule id {id}"));
+            }
+        }
+        PolicyCondition::And(conditions) | PolicyCondition::Or(conditions) => {
+            let operator = match condition {
+                PolicyCondition::And(_) => "and",
+                _ => "or",
+            };
+            if conditions.is_empty() {
+                report(&format!("{path}.{operator}"), format!("empty {operator}"));
+            }
+            for (position, condition) in conditions.iter().enumerate() {
+                let path = format!("{path}.{operator}[{position}]");
+                validate_condition(condition, &path, rule_ids, report);
+            }
+        }
+        PolicyCondition::Not(condition) => {
+            validate_condition(condition, &format!("{path}.not"), rule_ids, report)
+        }
+        PolicyCondition::KOfN { k, of } => {
+            if *k == 0 || *k > of.len() {
+                report(
+                    &format!("{path}.k_of_n.k"),
+                    format!("k {k} not in 1..={}", of.len()),
+                );
+            }
+            for (position, condition) in of.iter().enumerate() {
+                let path = format!("{path}.k_of_n.of[{position}]");
+                validate_condition(condition, &path, rule_ids, report);
+            }
+        }
+    }
+}
+
+fn validate_suppression(suppression: &Suppression, mut report: impl FnMut(&str, String)) {
+    if suppression.sha256.is_empty()
+        && suppression.path_glob.is_empty()
+        && suppression.rule_ids.is_empty()
+    {
+        report(
+            "sha256",
+            "suppression covers every hit, set sha256, path_glob or rule_ids".to_owned(),
+        );
+    }
+    let sha256 = suppression.sha256.trim();
+    if !sha256.is_empty() && (sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        report("sha256", format!("malformed sha256 digest {sha256}"));
+    }
+    if !suppression.path_glob.is_empty() {
+        if let Err(e) = build_glob(&suppression.path_glob) {
+            report("path_glob", format!("bad glob: {e}"));
+        }
+    }
+    if suppression.owner.trim().is_empty() {
+        report("owner", "empty owner".to_owned());
+    }
+    if suppression.reason.trim().is_empty() {
+        report("reason", "empty reason".to_owned());
+    }
+    if let Err(e) = parse_time(&suppression.expires) {
+        report("expires", e);
+    }
+}
+
+/// Positions of the actions already listed earlier in `actions`
+fn duplicate_positions(actions: &[RuleAction]) -> Vec<usize> {
+    (0..actions.len())
+        .filter(|&position| actions[..position].contains(&actions[position]))
+        .collect()
+}
+
+/// Targets passed as constants to `score(target_id)`
+fn score_targets(node: &Node) -> Vec<i64> {
+    node.iter()
+        .filter(|node| {
+            matches!(node.operator(), Operator::FunctionIdentifier { identifier } if identifier == "score")
+        })
+        .flat_map(|node| node.children().iter().flat_map(Node::iter))
+        .filter_map(|node| match node.operator() {
+            Operator::Const {
+                value: ExprValue::Int(target_id),
+            } => Some(*target_id),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Dictionary targets surface as `{location}{target_id}` and
+/// `score_{location}_{target_id}` variables
+fn is_target_referenced(expr_variables: &HashSet<String>, target_id: i32) -> bool {
+    let target_id = target_id.to_string();
+    expr_variables.iter().any(|variable| {
+        variable
+            .strip_suffix(&target_id)
+            .and_then(|location| location.chars().last())
+            .is_some_and(|c| !c.is_ascii_digit())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serde_json::json;
+
+    use super::{
+        is_target_referenced, load_file_scan_rule, load_suppression, validate_file_scan_rule,
+    };
+    use crate::{
+        fs_error::{Error, PolicyDiagnostic},
+        matcher::GlobalFileScanRule,
+        test_utils::{policy, rule},
+    };
+
+    /// Diagnostics of a policy with rules 1 and 2 after `edit`
+    fn diagnose(edit: fn(&mut GlobalFileScanRule)) -> Vec<PolicyDiagnostic> {
+        let mut file_scan_rule = policy(vec![rule(1, "body101 > 0"), rule(2, "body102 > 0")]);
+        edit(&mut file_scan_rule);
+        validate_file_scan_rule(&file_scan_rule)
+    }
+
+    #[test]
+    fn test_rule_diagnostics() {
+        type Case = (
+            fn(&mut GlobalFileScanRule),
+            &'static str,
+            Option<i32>,
+            &'static str,
+        );
+        let cases: [Case; 11] = [
+            (
+                |policy| policy.file_scan_rules[1].id = 1,
+                "$.file_scan_rules[1].id",
+                Some(1),
+                "duplicate rule id 1",
+            ),
+            (
+                |policy| {
+                    policy.file_scan_rules[0].min_file_size = 10;
+                    policy.file_scan_rules[0].max_file_size = 5;
+                },
+                "$.file_scan_rules[0].min_file_size",
+                Some(1),
+                "min_file_size 10 > max_file_size 5",
+            ),
+            (
+                |policy| policy.file_scan_rules[1].expr = "(body102 > 0".to_owned(),
+                "$.file_scan_rules[1].expr",
+                Some(2),
+                "bad expr syntax",
+            ),
+            (
+                |policy| {
+                    policy.file_scan_rules[0].path_filter.include.path_globs = vec!["a[".to_owned()]
+                },
+                "$.file_scan_rules[0].path_filter.include.path_globs[0]",
+                Some(1),
+                "bad glob",
+            ),
+            (
+                |policy| {
+                    policy.file_scan_rules[1].path_filter.exclude.name_regex = vec!["(".to_owned()]
+                },
+                "$.file_scan_rules[1].path_filter.exclude.name_regex[0]",
+                Some(2),
+                "bad regex",
+            ),
+            (
+                |policy| {
+                    policy.file_scan_rules[0].schedule =
+                        serde_json::from_value(json!({"weekdays": ["mon", "funday"]})).unwrap()
+                },
+                "$.file_scan_rules[0].schedule.weekdays[1]",
+                Some(1),
+                "funday",
+            ),
+            (
+                |policy| {
+                    policy.file_scan_rules[0].schedule =
+                        serde_json::from_value(json!({"hours": [{"start": 9, "end": 25}]})).unwrap()
+                },
+                "$.file_scan_rules[0].schedule.hours[0]",
+                Some(1),
+                "25",
+            ),
+            (
+                |policy| {
+                    policy.file_scan_rules[1].schedule =
+                        serde_json::from_value(json!({"timezone": "+25:00"})).unwrap()
+                },
+                "$.file_scan_rules[1].schedule.timezone",
+                Some(2),
+                "+25:00",
+            ),
+            (
+                |policy| {
+                    policy.suppressions = serde_json::from_value(json!([
+                        {"rule_ids": [1], "owner": " ", "reason": "false positive"}
+                    ]))
+                    .unwrap()
+                },
+                "$.suppressions[0].owner",
+                None,
+                "empty owner",
+            ),
+            (
+                |policy| {
+                    policy.composite_policies = serde_json::from_value(json!([{
+                        "id": 10, "code": "C10", "level": 1,
+                        "condition": {"and": [{"not": {"rule": 1}}, {"not": {"rule": 2}}]}
+                    }]))
+                    .unwrap()
+                },
+                "$.composite_policies[0].condition",
+                Some(10),
+                "holds without any hit",
+            ),
+            (
+                |policy| {
+                    policy.file_digital_dictionary =
+                        serde_json::from_value(json!({"101": {"value": 1}})).unwrap()
+                },
+                "$.file_digital_dictionary.101",
+                None,
+                "no dictionary target",
+            ),
+        ];
+        for (edit, path, rule_id, reason) in cases {
+            let diagnostics = diagnose(edit);
+            assert_eq!(diagnostics.len(), 1, "{path}: {diagnostics:?}");
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.document, "rule");
+            assert_eq!(diagnostic.path, path);
+            assert_eq!(diagnostic.rule_id, rule_id, "{path}");
+            assert!(
+                diagnostic.reason.contains(reason),
+                "{path}: {}",
+                diagnostic.reason
+            );
+        }
+    }
+
+    #[test]
+    fn test_unreferenced_target_is_accepted() {
+        let diagnostics = diagnose(|policy| {
+            policy.file_digital_dictionary = serde_json::from_value(json!({
+                "101": {"target_id": 900, "target_threshold": 1, "value": 1}
+            }))
+            .unwrap()
+        });
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_document_diagnostics() {
+        let policy = r#"{"config_version":"v1","file_digital_dictionary":{},"file_scan_rules":[
+            {"id":7,"code":"R7","level":"high"}
+        ]}"#;
+        let Err(Error::Policy(diagnostics)) = load_file_scan_rule(policy) else {
+            panic!("policy accepted");
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].document, "rule");
+        assert_eq!(diagnostics[0].path, "$.file_scan_rules[0].level");
+        assert_eq!(diagnostics[0].rule_id, Some(7));
+
+        let suppression = r#"{"path_glob":"a[","owner":"soc","reason":"r","expires":"soon"}"#;
+        let Err(Error::Policy(diagnostics)) = load_suppression(suppression) else {
+            panic!("suppression accepted");
+        };
+        let paths = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.document, diagnostic.path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![("suppression", "$.path_glob"), ("suppression", "$.expires")]
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.rule_id.is_none()));
+    }
+
+    #[test]
+    fn test_target_referenced() {
+        let variables = ["body9", "score_title_10", "body19", "x"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect::<HashSet<_>>();
+        assert!(is_target_referenced(&variables, 9));
+        assert!(is_target_referenced(&variables, 10));
+        assert!(is_target_referenced(&variables, 19));
+        // `body19` and `score_title_10` end with other ids
+        assert!(!is_target_referenced(&variables, 0));
+        assert!(!is_target_referenced(&variables, 1));
+        assert!(!is_target_referenced(&HashSet::new(), 9));
+    }
+}